@@ -0,0 +1,368 @@
+// Python bindings for the simulation core, built with maturin as the `boids`
+// module. See the README for an example.
+
+use boids::{
+    boids::{
+        Boid,
+        BoidType::{self, Predator, Prey},
+    },
+    model::{
+        registry, BoidOptions, DEFAULT_WORLD_SIZE, MAX_FORCE_RANGE, MAX_PREDATORS, MAX_PREY,
+        MODIFIER_RANGE, PROTECTION_RANGE, SPEED_RANGE, VISION_RANGE,
+    },
+    preset::{in_range, Preset},
+    simulation::{Environment, Simulation as Core},
+};
+use nannou::geom::Rect;
+use numpy::{ndarray::Array2, IntoPyArray, PyArray2};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+
+// A flock and its predators in a world of their own, stepped on demand.
+#[pyclass(module = "boids")]
+struct Simulation {
+    core: Core,
+    env: Environment,
+    seed: u64,
+}
+
+#[pymethods]
+impl Simulation {
+    // Keyword arguments left out keep the defaults, or the preset's values if
+    // a preset code is given.
+    #[new]
+    #[pyo3(signature = (
+        seed = 0,
+        width = DEFAULT_WORLD_SIZE.0,
+        height = DEFAULT_WORLD_SIZE.1,
+        preset = None,
+        prey = None,
+        predators = None,
+        alignment = None,
+        cohesion = None,
+        separation = None,
+        max_force = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        seed: u64,
+        width: f32,
+        height: f32,
+        preset: Option<&str>,
+        prey: Option<usize>,
+        predators: Option<usize>,
+        alignment: Option<f32>,
+        cohesion: Option<f32>,
+        separation: Option<f32>,
+        max_force: Option<f32>,
+    ) -> PyResult<Simulation> {
+        if !(width > 0.0 && height > 0.0 && width.is_finite() && height.is_finite()) {
+            return Err(PyValueError::new_err(
+                "width and height must be positive and finite",
+            ));
+        }
+        if let Some(prey) = prey {
+            check("prey", prey, &(0..=MAX_PREY))?;
+        }
+        if let Some(predators) = predators {
+            check("predators", predators, &(0..=MAX_PREDATORS))?;
+        }
+        if let Some(max_force) = max_force {
+            check("max_force", max_force, &MAX_FORCE_RANGE)?;
+        }
+        let world = Rect::from_w_h(width, height);
+        let registry = registry();
+        let mut core = Core::new(
+            seed,
+            world,
+            BoidOptions::prey(&registry),
+            BoidOptions::predators(&registry),
+        );
+        if let Some(code) = preset {
+            let preset = Preset::decode(code).map_err(PyValueError::new_err)?;
+            preset.blend_into(&mut core, 1.0);
+        }
+        if let Some(prey) = prey {
+            core.boid_options.flock_size = prey;
+        }
+        if let Some(predators) = predators {
+            core.predator_options.flock_size = predators;
+        }
+        if let Some(max_force) = max_force {
+            core.max_force = max_force;
+        }
+        let mut simulation = Simulation {
+            core,
+            env: Environment::headless(world),
+            seed,
+        };
+        for (name, weight) in [
+            ("Alignment", alignment),
+            ("Cohesion", cohesion),
+            ("Separation", separation),
+        ] {
+            if let Some(weight) = weight {
+                simulation.set_weight(name, weight, "prey")?;
+            }
+        }
+        simulation.restart(None);
+        Ok(simulation)
+    }
+
+    // Advances `steps` ticks and returns how many prey were caught on the way.
+    #[pyo3(signature = (steps = 1))]
+    fn step(&mut self, py: Python<'_>, steps: usize) -> usize {
+        let (core, env) = (&mut self.core, &self.env);
+        py.detach(|| {
+            (0..steps)
+                .map(|_| {
+                    core.step(env);
+                    core.catches.len()
+                })
+                .sum()
+        })
+    }
+
+    // Respawns every boid from `seed`, or from the seed it was last started with.
+    #[pyo3(signature = (seed = None))]
+    fn restart(&mut self, seed: Option<u64>) {
+        self.seed = seed.unwrap_or(self.seed);
+        let world = self.env.bounds;
+        self.core.restart(self.seed, world);
+        self.core.apply_settings(world, 0);
+    }
+
+    // Positions as an (n, 2) array, one row per boid of `species`.
+    #[pyo3(signature = (species = "prey"))]
+    fn positions<'py>(
+        &self,
+        py: Python<'py>,
+        species: &str,
+    ) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        Ok(rows(self.boids(species)?, |boid| boid.position.to_array()).into_pyarray(py))
+    }
+
+    #[pyo3(signature = (species = "prey"))]
+    fn velocities<'py>(
+        &self,
+        py: Python<'py>,
+        species: &str,
+    ) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        Ok(rows(self.boids(species)?, |boid| boid.velocity.to_array()).into_pyarray(py))
+    }
+
+    // Every steering behaviour a species has, switched on or not.
+    #[pyo3(signature = (species = "prey"))]
+    fn behaviours(&self, species: &str) -> PyResult<Vec<&'static str>> {
+        let options = self.core.options(parse_species(species)?);
+        Ok(options
+            .steering
+            .behaviours
+            .iter()
+            .map(|b| b.behaviour.name())
+            .collect())
+    }
+
+    // The weight of a behaviour, or 0.0 if it's switched off.
+    #[pyo3(signature = (name, species = "prey"))]
+    fn weight(&self, name: &str, species: &str) -> PyResult<f32> {
+        let options = self.core.options(parse_species(species)?);
+        if !options
+            .steering
+            .behaviours
+            .iter()
+            .any(|b| b.behaviour.name() == name)
+        {
+            return Err(unknown_behaviour(name, options));
+        }
+        Ok(options.steering.weight(name))
+    }
+
+    // Changes a behaviour's weight, taking effect from the next step.
+    #[pyo3(signature = (name, weight, species = "prey"))]
+    fn set_weight(&mut self, name: &str, weight: f32, species: &str) -> PyResult<()> {
+        check("weight", weight, &MODIFIER_RANGE)?;
+        let options = self.options_mut(species)?;
+        match options.steering.weight_mut(name) {
+            Some(w) => {
+                *w = weight;
+                Ok(())
+            }
+            None => Err(unknown_behaviour(name, options)),
+        }
+    }
+
+    #[pyo3(signature = (name, enabled = true, species = "prey"))]
+    fn enable(&mut self, name: &str, enabled: bool, species: &str) -> PyResult<()> {
+        let options = self.options_mut(species)?;
+        match options
+            .steering
+            .behaviours
+            .iter_mut()
+            .find(|b| b.behaviour.name() == name)
+        {
+            Some(b) => {
+                b.enabled = enabled;
+                Ok(())
+            }
+            None => Err(unknown_behaviour(name, options)),
+        }
+    }
+
+    // Changes a species' parameters mid-run. Adding or removing boids keeps
+    // the rest where they are.
+    #[pyo3(signature = (
+        species,
+        count = None,
+        min_speed = None,
+        max_speed = None,
+        visual_range = None,
+        protected_range = None,
+    ))]
+    fn set_species(
+        &mut self,
+        species: &str,
+        count: Option<usize>,
+        min_speed: Option<f32>,
+        max_speed: Option<f32>,
+        visual_range: Option<f32>,
+        protected_range: Option<f32>,
+    ) -> PyResult<()> {
+        let max_count = match parse_species(species)? {
+            Prey => MAX_PREY,
+            Predator => MAX_PREDATORS,
+        };
+        if let Some(count) = count {
+            check("count", count, &(0..=max_count))?;
+        }
+        for (name, value, range) in [
+            ("min_speed", min_speed, SPEED_RANGE),
+            ("max_speed", max_speed, SPEED_RANGE),
+            ("visual_range", visual_range, VISION_RANGE),
+            ("protected_range", protected_range, PROTECTION_RANGE),
+        ] {
+            if let Some(value) = value {
+                check(name, value, &range)?;
+            }
+        }
+        let options = self.options_mut(species)?;
+        if let Some(count) = count {
+            options.n_mod = i32::try_from(count)
+                .ok()
+                .zip(i32::try_from(options.flock_size).ok())
+                .map(|(count, size)| count - size)
+                .ok_or_else(|| PyValueError::new_err("count is too large"))?;
+        }
+        let fields = [
+            (&mut options.min_speed, min_speed),
+            (&mut options.max_speed, max_speed),
+            (&mut options.visual_range, visual_range),
+            (&mut options.protected_range, protected_range),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        self.core.apply_settings(self.env.bounds, 0);
+        Ok(())
+    }
+
+    #[getter]
+    fn max_force(&self) -> f32 {
+        self.core.max_force
+    }
+
+    #[setter]
+    fn set_max_force(&mut self, max_force: f32) -> PyResult<()> {
+        check("max_force", max_force, &MAX_FORCE_RANGE)?;
+        self.core.max_force = max_force;
+        self.core.apply_settings(self.env.bounds, 0);
+        Ok(())
+    }
+
+    // Polarisation, mean speed, mean neighbours and cluster count for the prey.
+    fn metrics<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.core.rebuild_grid();
+        let metrics = self.core.metrics();
+        let dict = PyDict::new(py);
+        dict.set_item("polarisation", metrics.polarisation)?;
+        dict.set_item("mean_speed", metrics.mean_speed)?;
+        dict.set_item("mean_neighbours", metrics.mean_neighbours)?;
+        dict.set_item("clusters", metrics.clusters)?;
+        Ok(dict)
+    }
+
+    // The current settings as a preset code, to open in the app.
+    fn preset(&self) -> String {
+        Preset::of(&self.core, "Normal").encode()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Simulation(seed={}, prey={}, predators={})",
+            self.seed,
+            self.core.flock.len(),
+            self.core.predators.len()
+        )
+    }
+}
+
+impl Simulation {
+    fn boids(&self, species: &str) -> PyResult<&[Boid]> {
+        Ok(match parse_species(species)? {
+            Prey => &self.core.flock,
+            Predator => &self.core.predators,
+        })
+    }
+
+    fn options_mut(&mut self, species: &str) -> PyResult<&mut BoidOptions> {
+        Ok(match parse_species(species)? {
+            Prey => &mut self.core.boid_options,
+            Predator => &mut self.core.predator_options,
+        })
+    }
+}
+
+// Values from Python are held to the same limits as the control panel and
+// preset codes, so a NaN or negative speed can't quietly spoil the run.
+fn check<T: PartialOrd + std::fmt::Display>(
+    name: &str,
+    value: T,
+    range: &std::ops::RangeInclusive<T>,
+) -> PyResult<()> {
+    in_range(name, value, range).map_err(PyValueError::new_err)
+}
+
+fn parse_species(species: &str) -> PyResult<BoidType> {
+    match species.to_ascii_lowercase().as_str() {
+        "prey" => Ok(Prey),
+        "predator" | "predators" => Ok(Predator),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown species {species:?}, expected \"prey\" or \"predators\""
+        ))),
+    }
+}
+
+fn unknown_behaviour(name: &str, options: &BoidOptions) -> PyErr {
+    let names: Vec<&str> = options
+        .steering
+        .behaviours
+        .iter()
+        .map(|b| b.behaviour.name())
+        .collect();
+    PyValueError::new_err(format!(
+        "unknown behaviour {name:?}, expected one of {}",
+        names.join(", ")
+    ))
+}
+
+fn rows(boids: &[Boid], row: impl Fn(&Boid) -> [f32; 2]) -> Array2<f32> {
+    Array2::from_shape_fn((boids.len(), 2), |(i, j)| row(&boids[i])[j])
+}
+
+#[pymodule]
+#[pyo3(name = "boids")]
+fn boids_python(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Simulation>()?;
+    Ok(())
+}
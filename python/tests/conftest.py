@@ -0,0 +1,15 @@
+# Builds the extension into the active virtualenv before any test imports it,
+# so the tests always run against the current Rust code.
+
+import pathlib
+import subprocess
+import sys
+
+
+def pytest_sessionstart(session):
+    python_dir = pathlib.Path(__file__).resolve().parent.parent
+    subprocess.run(
+        [sys.executable, "-m", "maturin", "develop"],
+        cwd=python_dir,
+        check=True,
+    )
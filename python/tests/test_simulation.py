@@ -0,0 +1,65 @@
+import math
+
+import numpy as np
+import pytest
+
+import boids
+
+
+def test_array_shapes():
+    sim = boids.Simulation(seed=1, prey=40, predators=2)
+    sim.step(3)
+    for species, count in [("prey", 40), ("predators", 2)]:
+        for array in [sim.positions(species), sim.velocities(species)]:
+            assert array.shape == (count, 2)
+            assert array.dtype == np.float32
+            assert np.isfinite(array).all()
+
+
+def test_set_weight_takes_effect():
+    plain = boids.Simulation(seed=3, prey=60)
+    tight = boids.Simulation(seed=3, prey=60)
+    tight.set_weight("Cohesion", 2.0)
+    assert tight.weight("Cohesion") == 2.0
+    plain.step(50)
+    tight.step(50)
+    assert not np.array_equal(plain.positions(), tight.positions())
+
+
+def test_same_seed_same_run():
+    first = boids.Simulation(seed=5, prey=30)
+    second = boids.Simulation(seed=5, prey=30)
+    first.step(20)
+    second.step(20)
+    assert np.array_equal(first.positions(), second.positions())
+
+
+@pytest.mark.parametrize(
+    "call",
+    [
+        lambda sim: sim.positions("fish"),
+        lambda sim: sim.velocities("fish"),
+        lambda sim: sim.set_weight("Cohesion", 1.0, species="fish"),
+        lambda sim: sim.set_species("fish", count=10),
+    ],
+)
+def test_unknown_species_raises(call):
+    with pytest.raises(ValueError):
+        call(boids.Simulation())
+
+
+@pytest.mark.parametrize(
+    "call",
+    [
+        lambda sim: sim.set_species("prey", min_speed=math.nan),
+        lambda sim: sim.set_species("prey", visual_range=-1.0),
+        lambda sim: sim.set_species("predators", count=1000),
+        lambda sim: sim.set_weight("Cohesion", math.inf),
+        lambda sim: setattr(sim, "max_force", -0.5),
+        lambda sim: sim.set_weight("NoSuchBehaviour", 1.0),
+        lambda sim: sim.weight("cohesion"),
+    ],
+)
+def test_bad_values_raise(call):
+    with pytest.raises(ValueError):
+        call(boids.Simulation())
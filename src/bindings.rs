@@ -0,0 +1,506 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use nannou::{event::ModifiersState, io::load_from_toml, prelude::Key};
+
+// Everything the keyboard can do, independent of which key does it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    AddBoids,
+    RemoveBoids,
+    AddPredator,
+    RemovePredator,
+    AlignmentDown,
+    AlignmentUp,
+    CohesionDown,
+    CohesionUp,
+    SeparationDown,
+    SeparationUp,
+    CycleCursorMode,
+    CycleTheme,
+    CycleNeighbourhood,
+    CyclePredatorNeighbourhood,
+    RemoveLeader,
+    AddLeader,
+    CycleLeaderTarget,
+    ToggleFollowQueue,
+    CyclePathMode,
+    ClearPath,
+    CycleDebugOverlay,
+    ToggleTrails,
+    CycleColourMode,
+    CycleGradient,
+    Screenshot,
+    ToggleRecording,
+    ToggleText,
+    // Switches to one of the built-in presets, counting from zero.
+    LoadPreset(usize),
+    ExportPreset,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    ResetView,
+    CycleResizeMode,
+    TogglePause,
+    StepOnce,
+    SlowDown,
+    SpeedUp,
+    ScrubBack,
+    ScrubForward,
+    ToggleComparison,
+    SwitchSide,
+    Restart,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 50] = [
+        Action::AddBoids,
+        Action::RemoveBoids,
+        Action::AddPredator,
+        Action::RemovePredator,
+        Action::AlignmentDown,
+        Action::AlignmentUp,
+        Action::CohesionDown,
+        Action::CohesionUp,
+        Action::SeparationDown,
+        Action::SeparationUp,
+        Action::CycleCursorMode,
+        Action::CycleTheme,
+        Action::CycleNeighbourhood,
+        Action::CyclePredatorNeighbourhood,
+        Action::RemoveLeader,
+        Action::AddLeader,
+        Action::CycleLeaderTarget,
+        Action::ToggleFollowQueue,
+        Action::CyclePathMode,
+        Action::ClearPath,
+        Action::CycleDebugOverlay,
+        Action::ToggleTrails,
+        Action::CycleColourMode,
+        Action::CycleGradient,
+        Action::Screenshot,
+        Action::ToggleRecording,
+        Action::ToggleText,
+        Action::LoadPreset(0),
+        Action::LoadPreset(1),
+        Action::LoadPreset(2),
+        Action::LoadPreset(3),
+        Action::LoadPreset(4),
+        Action::LoadPreset(5),
+        Action::ExportPreset,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::PanUp,
+        Action::PanDown,
+        Action::ResetView,
+        Action::CycleResizeMode,
+        Action::TogglePause,
+        Action::StepOnce,
+        Action::SlowDown,
+        Action::SpeedUp,
+        Action::ScrubBack,
+        Action::ScrubForward,
+        Action::ToggleComparison,
+        Action::SwitchSide,
+        Action::Restart,
+        Action::Quit,
+    ];
+
+    // Actions that come in pairs share a label, and are shown on one line of the instructions.
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::AddBoids | Action::RemoveBoids => "Add/Remove Boids",
+            Action::AddPredator | Action::RemovePredator => "Add/Remove Predators",
+            Action::AlignmentDown | Action::AlignmentUp => "Alignment",
+            Action::CohesionDown | Action::CohesionUp => "Cohesion",
+            Action::SeparationDown | Action::SeparationUp => "Separation",
+            Action::CycleCursorMode => "Cursor Mode",
+            Action::CycleTheme => "Theme",
+            Action::CycleNeighbourhood => "Neighbourhood",
+            Action::CyclePredatorNeighbourhood => "Predator Neighbourhood",
+            Action::RemoveLeader | Action::AddLeader => "Leaders",
+            Action::CycleLeaderTarget => "Leader Target",
+            Action::ToggleFollowQueue => "Follow Queue",
+            Action::CyclePathMode => "Path Mode",
+            Action::ClearPath => "Clear Path",
+            Action::CycleDebugOverlay => "Debug Overlay",
+            Action::ToggleTrails => "Trails",
+            Action::CycleColourMode => "Colour Mode",
+            Action::CycleGradient => "Gradient",
+            Action::Screenshot => "Screenshot",
+            Action::ToggleRecording => "Record Video and Sound",
+            Action::ToggleText => "Hide Text",
+            Action::LoadPreset(_) => "Presets",
+            Action::ExportPreset => "Share Preset Code",
+            Action::PanLeft | Action::PanRight | Action::PanUp | Action::PanDown => "Pan",
+            Action::ResetView => "Reset View",
+            Action::CycleResizeMode => "Resize Mode",
+            Action::TogglePause => "Pause",
+            Action::StepOnce => "Step",
+            Action::SlowDown | Action::SpeedUp => "Slower/Faster",
+            Action::ScrubBack | Action::ScrubForward => "Rewind/Forward",
+            Action::ToggleComparison => "Compare Side by Side",
+            Action::SwitchSide => "Switch Side",
+            Action::Restart => "Restart",
+            Action::Quit => "Quit",
+        }
+    }
+
+    // The name used in the bindings file, e.g. "CycleTheme" or "Preset3".
+    pub fn name(self) -> String {
+        match self {
+            Action::LoadPreset(i) => format!("Preset{}", i + 1),
+            action => format!("{action:?}"),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+// A key plus the modifiers that must be held with it, e.g. "Shift+Up".
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct KeyCombo {
+    pub key: Key,
+    pub mods: ModifiersState,
+}
+
+impl KeyCombo {
+    pub fn new(key: Key) -> KeyCombo {
+        KeyCombo {
+            key,
+            mods: ModifiersState::empty(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<KeyCombo, String> {
+        let mut mods = ModifiersState::empty();
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_name = parts.pop().unwrap_or_default();
+        for part in parts {
+            mods |= match part.to_ascii_lowercase().as_str() {
+                "shift" => ModifiersState::SHIFT,
+                "ctrl" | "control" => ModifiersState::CTRL,
+                "alt" => ModifiersState::ALT,
+                "logo" | "super" | "cmd" => ModifiersState::LOGO,
+                _ => return Err(format!("unknown modifier {part:?} in {text:?}")),
+            };
+        }
+        KEY_NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(key_name))
+            .map(|(key, _)| KeyCombo { key: *key, mods })
+            .ok_or_else(|| format!("unknown key {key_name:?} in {text:?}"))
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (ModifiersState::CTRL, "Ctrl+"),
+            (ModifiersState::ALT, "Alt+"),
+            (ModifiersState::LOGO, "Super+"),
+            (ModifiersState::SHIFT, "Shift+"),
+        ] {
+            if self.mods.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        let name = KEY_NAMES
+            .iter()
+            .find(|(key, _)| *key == self.key)
+            .map_or("?", |(_, name)| name);
+        // Letters are shown in lower case, like the rest of the on-screen instructions.
+        if name.len() == 1 {
+            write!(f, "{}", name.to_ascii_lowercase())
+        } else {
+            f.write_str(name)
+        }
+    }
+}
+
+pub struct Bindings {
+    keys: Vec<(KeyCombo, Action)>,
+}
+
+impl Bindings {
+    pub fn defaults() -> Bindings {
+        let keys = [
+            (Key::Up, Action::AddBoids),
+            (Key::Down, Action::RemoveBoids),
+            (Key::Right, Action::AddPredator),
+            (Key::Left, Action::RemovePredator),
+            (Key::U, Action::AlignmentDown),
+            (Key::I, Action::AlignmentUp),
+            (Key::J, Action::CohesionDown),
+            (Key::K, Action::CohesionUp),
+            (Key::M, Action::SeparationDown),
+            (Key::Comma, Action::SeparationUp),
+            (Key::C, Action::CycleCursorMode),
+            (Key::T, Action::CycleTheme),
+            (Key::N, Action::CycleNeighbourhood),
+            (Key::LBracket, Action::RemoveLeader),
+            (Key::RBracket, Action::AddLeader),
+            (Key::L, Action::CycleLeaderTarget),
+            (Key::O, Action::ToggleFollowQueue),
+            (Key::P, Action::CyclePathMode),
+            (Key::Back, Action::ClearPath),
+            (Key::D, Action::CycleDebugOverlay),
+            (Key::E, Action::ToggleTrails),
+            (Key::V, Action::CycleColourMode),
+            (Key::G, Action::CycleGradient),
+            (Key::S, Action::Screenshot),
+            (Key::H, Action::ToggleText),
+            (Key::Key1, Action::LoadPreset(0)),
+            (Key::Key2, Action::LoadPreset(1)),
+            (Key::Key3, Action::LoadPreset(2)),
+            (Key::Key4, Action::LoadPreset(3)),
+            (Key::Key5, Action::LoadPreset(4)),
+            (Key::Key6, Action::LoadPreset(5)),
+            (Key::W, Action::ExportPreset),
+            (Key::R, Action::ResetView),
+            (Key::Space, Action::TogglePause),
+            (Key::Period, Action::StepOnce),
+            (Key::Minus, Action::SlowDown),
+            (Key::Equals, Action::SpeedUp),
+            (Key::Z, Action::ScrubBack),
+            (Key::X, Action::ScrubForward),
+            (Key::A, Action::ToggleComparison),
+            (Key::Tab, Action::SwitchSide),
+            (Key::F5, Action::Restart),
+            (Key::Q, Action::Quit),
+        ];
+        // These sit on Shift, since the keys they'd naturally go on are taken.
+        let shifted = [
+            (Key::Left, Action::PanLeft),
+            (Key::Right, Action::PanRight),
+            (Key::Up, Action::PanUp),
+            (Key::Down, Action::PanDown),
+            (Key::R, Action::CycleResizeMode),
+            (Key::N, Action::CyclePredatorNeighbourhood),
+            (Key::S, Action::ToggleRecording),
+        ];
+        Bindings {
+            keys: keys
+                .into_iter()
+                .map(|(key, action)| (KeyCombo::new(key), action))
+                .chain(shifted.into_iter().map(|(key, action)| {
+                    let combo = KeyCombo {
+                        key,
+                        mods: ModifiersState::SHIFT,
+                    };
+                    (combo, action)
+                }))
+                .collect(),
+        }
+    }
+
+    // The defaults with `path` layered on top. A missing file just means the
+    // defaults; one that doesn't parse is reported and ignored.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Bindings {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Bindings::defaults();
+        }
+        Bindings::load(path).unwrap_or_else(|e| {
+            eprintln!("Ignoring key bindings in {}: {e}", path.display());
+            Bindings::defaults()
+        })
+    }
+
+    // Each entry maps a key combination to an action name, or to "None" to
+    // unbind a default.
+    pub fn load(path: &Path) -> Result<Bindings, String> {
+        let file: BTreeMap<String, String> = load_from_toml(path).map_err(|e| e.to_string())?;
+        let mut bindings = Bindings::defaults();
+        for (combo, action) in file {
+            let combo = KeyCombo::parse(&combo)?;
+            bindings.keys.retain(|(bound, _)| *bound != combo);
+            if action.eq_ignore_ascii_case("none") {
+                continue;
+            }
+            let action = Action::from_name(&action)
+                .ok_or_else(|| format!("unknown action {action:?} for {combo}"))?;
+            bindings.keys.push((combo, action));
+        }
+        Ok(bindings)
+    }
+
+    pub fn action(&self, key: Key, mods: ModifiersState) -> Option<Action> {
+        self.keys
+            .iter()
+            .find(|(combo, _)| combo.key == key && combo.mods == mods)
+            .map(|(_, action)| *action)
+    }
+
+    fn keys_for(&self, action: Action) -> impl Iterator<Item = KeyCombo> + '_ {
+        self.keys
+            .iter()
+            .filter(move |(_, bound)| *bound == action)
+            .map(|(combo, _)| *combo)
+    }
+
+    // One line per label, e.g. "u/i   Alignment", leaving out anything unbound.
+    pub fn instructions(&self) -> Vec<(String, &'static str)> {
+        let mut lines: Vec<(String, &'static str)> = Vec::new();
+        for action in Action::ALL {
+            let keys = self
+                .keys_for(action)
+                .map(|combo| combo.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            if keys.is_empty() {
+                continue;
+            }
+            match lines.last_mut() {
+                Some((existing, label)) if *label == action.label() => {
+                    existing.push('/');
+                    existing.push_str(&keys);
+                }
+                _ => lines.push((keys, action.label())),
+            }
+        }
+        lines
+    }
+}
+
+// Names used for keys in the bindings file. Lookups ignore case.
+const KEY_NAMES: &[(Key, &str)] = &[
+    (Key::A, "A"),
+    (Key::B, "B"),
+    (Key::C, "C"),
+    (Key::D, "D"),
+    (Key::E, "E"),
+    (Key::F, "F"),
+    (Key::G, "G"),
+    (Key::H, "H"),
+    (Key::I, "I"),
+    (Key::J, "J"),
+    (Key::K, "K"),
+    (Key::L, "L"),
+    (Key::M, "M"),
+    (Key::N, "N"),
+    (Key::O, "O"),
+    (Key::P, "P"),
+    (Key::Q, "Q"),
+    (Key::R, "R"),
+    (Key::S, "S"),
+    (Key::T, "T"),
+    (Key::U, "U"),
+    (Key::V, "V"),
+    (Key::W, "W"),
+    (Key::X, "X"),
+    (Key::Y, "Y"),
+    (Key::Z, "Z"),
+    (Key::Key0, "0"),
+    (Key::Key1, "1"),
+    (Key::Key2, "2"),
+    (Key::Key3, "3"),
+    (Key::Key4, "4"),
+    (Key::Key5, "5"),
+    (Key::Key6, "6"),
+    (Key::Key7, "7"),
+    (Key::Key8, "8"),
+    (Key::Key9, "9"),
+    (Key::F1, "F1"),
+    (Key::F2, "F2"),
+    (Key::F3, "F3"),
+    (Key::F4, "F4"),
+    (Key::F5, "F5"),
+    (Key::F6, "F6"),
+    (Key::F7, "F7"),
+    (Key::F8, "F8"),
+    (Key::F9, "F9"),
+    (Key::F10, "F10"),
+    (Key::F11, "F11"),
+    (Key::F12, "F12"),
+    (Key::Up, "Up"),
+    (Key::Down, "Down"),
+    (Key::Left, "Left"),
+    (Key::Right, "Right"),
+    (Key::Space, "Space"),
+    (Key::Return, "Enter"),
+    (Key::Back, "Backspace"),
+    (Key::Tab, "Tab"),
+    (Key::Escape, "Escape"),
+    (Key::Delete, "Delete"),
+    (Key::Insert, "Insert"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::Comma, ","),
+    (Key::Period, "."),
+    (Key::Slash, "/"),
+    (Key::Backslash, "\\"),
+    (Key::Semicolon, ";"),
+    (Key::Apostrophe, "'"),
+    (Key::LBracket, "["),
+    (Key::RBracket, "]"),
+    (Key::Minus, "-"),
+    (Key::Equals, "="),
+    (Key::Grave, "`"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_keys() {
+        assert_eq!(KeyCombo::parse("a"), Ok(KeyCombo::new(Key::A)));
+        assert_eq!(KeyCombo::parse("F5"), Ok(KeyCombo::new(Key::F5)));
+        assert_eq!(KeyCombo::parse(","), Ok(KeyCombo::new(Key::Comma)));
+        assert_eq!(KeyCombo::parse(" Space "), Ok(KeyCombo::new(Key::Space)));
+    }
+
+    #[test]
+    fn ignores_case() {
+        let shift_up = KeyCombo {
+            key: Key::Up,
+            mods: ModifiersState::SHIFT,
+        };
+        for text in ["Shift+Up", "shift+up", "SHIFT+UP", "Shift + up"] {
+            assert_eq!(KeyCombo::parse(text), Ok(shift_up), "{text}");
+        }
+        assert_eq!(KeyCombo::parse("backspace"), Ok(KeyCombo::new(Key::Back)));
+    }
+
+    #[test]
+    fn combines_modifiers() {
+        let combo = KeyCombo::parse("Ctrl+Alt+Shift+S").unwrap();
+        assert_eq!(combo.key, Key::S);
+        assert_eq!(
+            combo.mods,
+            ModifiersState::CTRL | ModifiersState::ALT | ModifiersState::SHIFT
+        );
+        for (text, mods) in [
+            ("Control+x", ModifiersState::CTRL),
+            ("Cmd+x", ModifiersState::LOGO),
+            ("Super+x", ModifiersState::LOGO),
+            ("Logo+x", ModifiersState::LOGO),
+        ] {
+            assert_eq!(KeyCombo::parse(text).unwrap().mods, mods, "{text}");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_modifiers() {
+        assert!(KeyCombo::parse("Hyper+a").is_err());
+        assert!(KeyCombo::parse("Shift+Banana").is_err());
+        assert!(KeyCombo::parse("").is_err());
+        assert!(KeyCombo::parse("Shift+").is_err());
+        assert!(KeyCombo::parse("ab").is_err());
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for (combo, _) in Bindings::defaults().keys {
+            assert_eq!(KeyCombo::parse(&combo.to_string()), Ok(combo));
+        }
+    }
+}
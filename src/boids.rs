@@ -1,6 +1,16 @@
+use std::collections::VecDeque;
+
 use nannou::prelude::*;
 
-use crate::{cursor::CursorMode, model::Model, theme::Theme};
+use crate::{
+    colour::ColourMode,
+    cursor::CursorMode,
+    leader::{self, Leader, LeaderTarget},
+    model::Model,
+    neighbourhood::Neighbourhood,
+    spatial::SpatialGrid,
+    theme::faded,
+};
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum BoidType {
@@ -9,13 +19,6 @@ pub enum BoidType {
 }
 
 impl BoidType {
-    fn default_color(self) -> Rgb8 {
-        match self {
-            BoidType::Prey => BLACK,
-            BoidType::Predator => DARKRED,
-        }
-    }
-
     fn visual_range(self) -> f32 {
         match self {
             BoidType::Prey => 80.0,
@@ -37,29 +40,66 @@ impl BoidType {
     }
 }
 
-#[derive(PartialEq)]
+// Each steering contribution from the last update, named after the behaviour
+// that made it, kept for inspection and debugging.
+#[derive(PartialEq, Clone, Default)]
+pub struct Forces {
+    named: Vec<(&'static str, Vec2)>,
+}
+
+impl Forces {
+    pub fn named(&self) -> &[(&'static str, Vec2)] {
+        &self.named
+    }
+
+    // Keeps the allocation, since forces are replaced every step.
+    pub fn clear(&mut self) {
+        self.named.clear();
+    }
+
+    pub fn push(&mut self, name: &'static str, force: Vec2) {
+        self.named.push((name, force));
+    }
+
+    pub fn total(&self) -> Vec2 {
+        self.named()
+            .iter()
+            .fold(Vec2::ZERO, |sum, (_, force)| sum + *force)
+    }
+}
+
+#[derive(PartialEq, Clone)]
 pub struct Boid {
     pub b_type: BoidType,
-    pub color: Rgb8,
     pub width: f32,
     pub height: f32,
     pub position: Vec2,
     pub velocity: Vec2,
     pub acceleration: Vec2,
     pub neighbour_count: usize,
+    pub density: f32,
+    pub cluster: usize,
+    pub age: u32,
+    pub forces: Forces,
     pub max_force: f32,
     pub max_speed: f32,
     pub min_speed: f32,
     pub visual_range: f32,
     pub protected_range: f32,
+    pub leader: Option<Leader>,
+    // Steering chosen from outside, by a learning agent, in place of the flock rules.
+    pub action: Option<Vec2>,
+    // Whether a predator is within catching distance, so each catch is only counted once.
+    pub caught: bool,
+    // Recent positions, oldest first. `None` marks where the boid wrapped.
+    pub trail: VecDeque<Option<Vec2>>,
 }
 
 impl Boid {
     pub fn new(x: f32, y: f32, boid_type: BoidType) -> Boid {
-        let color = boid_type.default_color();
         let (width, height) = boid_type.size();
         let position = vec2(x, y);
-        let velocity = vec2(random_range(-1.0, 1.0), random_range(-1.0, 1.0));
+        let velocity = Vec2::ZERO;
         let acceleration = vec2(0.0, 0.0);
         let neighbour_count = 0;
         let max_force = 0.2;
@@ -69,18 +109,34 @@ impl Boid {
         let protected_range = 30.0;
         Boid {
             b_type: boid_type,
-            color,
             width,
             height,
             position,
             velocity,
             neighbour_count,
+            density: 0.0,
+            cluster: 0,
+            age: 0,
+            forces: Forces::default(),
             acceleration,
             max_force,
             max_speed,
             min_speed,
             visual_range,
             protected_range,
+            leader: None,
+            action: None,
+            caught: false,
+            trail: VecDeque::new(),
+        }
+    }
+
+    // How much this boid counts towards its neighbours' alignment and cohesion.
+    pub fn influence(&self, leader_weight: f32) -> f32 {
+        if self.leader.is_some() {
+            leader_weight
+        } else {
+            1.0
         }
     }
 
@@ -96,18 +152,116 @@ impl Boid {
             sum
         })
     }
-    #[allow(clippy::cast_precision_loss)]
-    pub fn cohere(&self, nearby_boids: &[&Boid]) -> Vec2 {
-        let average_position = nearby_boids
-            .iter()
-            .fold(Vec2::ZERO, |sum, &boid| sum + boid.position);
+    pub fn cohere(&self, nearby_boids: &[&Boid], leader_weight: f32) -> Vec2 {
+        let (weighted_position, total_weight) =
+            nearby_boids
+                .iter()
+                .fold((Vec2::ZERO, 0.0), |(sum, total), &boid| {
+                    let weight = boid.influence(leader_weight);
+                    (sum + boid.position * weight, total + weight)
+                });
 
-        if average_position == Vec2::ZERO {
-            average_position
+        if total_weight == 0.0 {
+            Vec2::ZERO
         } else {
-            // precision loss no big deal when nearby_boids is <=1000
-            let len = nearby_boids.len() as f32;
-            (average_position / len - self.position).normalize()
+            (weighted_position / total_weight - self.position).normalize_or_zero()
+        }
+    }
+
+    pub fn seek(&self, target: Vec2) -> Vec2 {
+        let desired = (target - self.position).normalize_or_zero() * self.max_speed;
+        (desired - self.velocity).clamp_length_max(self.max_force)
+    }
+
+    // Steering for a leader boid, which ignores the flock and heads for its own target.
+    // `wander` is the random nudge to the wander angle this step.
+    pub fn lead(
+        &mut self,
+        target: LeaderTarget,
+        bounds: &Rect,
+        cursor_pos: Vec2,
+        wander: f32,
+    ) -> Vec2 {
+        let Some(mut leader) = self.leader else {
+            return Vec2::ZERO;
+        };
+        let force = match target {
+            LeaderTarget::Wander => {
+                // Steer towards a point on a circle projected ahead, nudged a little each step.
+                leader.wander_angle += wander;
+                let ahead = self.position + self.velocity.normalize_or_zero() * 60.0;
+                let offset = vec2(leader.wander_angle.cos(), leader.wander_angle.sin()) * 30.0;
+                self.seek(ahead + offset)
+            }
+            LeaderTarget::Waypoints => {
+                let waypoints = leader::waypoints(bounds);
+                let mut waypoint = waypoints[leader.waypoint % waypoints.len()];
+                if self.position.distance(waypoint) < self.visual_range {
+                    leader.waypoint = (leader.waypoint + 1) % waypoints.len();
+                    waypoint = waypoints[leader.waypoint];
+                }
+                self.seek(waypoint)
+            }
+            LeaderTarget::Cursor => self.seek(cursor_pos),
+        };
+        self.leader = Some(leader);
+        force
+    }
+
+    // Fall in behind the nearest visible leader, and get out of its way if in front of it.
+    pub fn follow_leader(&self, nearby_boids: &[&Boid]) -> Vec2 {
+        let Some(leader) = nearby_boids
+            .iter()
+            .filter(|boid| boid.leader.is_some())
+            .min_by(|a, b| {
+                let a = a.position.distance(self.position);
+                let b = b.position.distance(self.position);
+                a.total_cmp(&b)
+            })
+        else {
+            return Vec2::ZERO;
+        };
+
+        let heading = leader.velocity.normalize_or_zero();
+        let behind = leader.position - heading * leader.height * 3.0;
+        let to_self = self.position - leader.position;
+        let in_the_way =
+            to_self.dot(heading) > 0.0 && to_self.length() < self.protected_range * 2.0;
+        if in_the_way {
+            // Step sideways out of the leader's path.
+            let side = vec2(-heading.y, heading.x);
+            let side = if to_self.dot(side) >= 0.0 {
+                side
+            } else {
+                -side
+            };
+            return self.seek(self.position + side * self.visual_range);
+        }
+        self.seek(behind)
+    }
+
+    // Reynolds path following: look ahead, project onto the path, and only steer
+    // back towards it once the predicted position has left the corridor.
+    pub fn follow_path(&self, path: &[Vec2], width: f32) -> Vec2 {
+        let predicted = self.position + self.velocity.normalize_or_zero() * 25.0;
+        let closest = path
+            .windows(2)
+            .map(|segment| {
+                let (start, end) = (segment[0], segment[1]);
+                let direction = (end - start).normalize_or_zero();
+                let along = (predicted - start)
+                    .dot(direction)
+                    .clamp(0.0, start.distance(end));
+                let normal_point = start + direction * along;
+                (predicted.distance(normal_point), normal_point, direction)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        match closest {
+            Some((distance, normal_point, direction)) if distance > width / 2.0 => {
+                self.seek(normal_point + direction * 20.0)
+            }
+            _ => Vec2::ZERO,
         }
     }
 
@@ -117,6 +271,7 @@ impl Boid {
         let right = win.right() + pad;
         let top = win.top() + pad;
         let bottom = win.bottom() - pad;
+        let before = self.position;
 
         self.position.x = match self.position.x {
             x if x > right => left,
@@ -128,9 +283,31 @@ impl Boid {
             y if y < bottom => top,
             y => y,
         };
+        // Break the trail rather than streak it across the screen.
+        if self.position != before && !self.trail.is_empty() {
+            self.trail.push_back(None);
+        }
     }
 
-    pub fn avoid_bounds(&mut self, win: &Rect) -> Vec2 {
+    // Pulls a boid that's ended up outside `bounds` back to the nearest edge.
+    pub fn keep_within(&mut self, bounds: &Rect) {
+        let before = self.position;
+        self.position = self
+            .position
+            .clamp(bounds.bottom_left(), bounds.top_right());
+        if self.position != before && !self.trail.is_empty() {
+            self.trail.push_back(None);
+        }
+    }
+
+    pub fn record_trail(&mut self, length: usize) {
+        self.trail.push_back(Some(self.position));
+        while self.trail.len() > length {
+            self.trail.pop_front();
+        }
+    }
+
+    pub fn avoid_bounds(&self, win: &Rect) -> Vec2 {
         let margin = 50.0;
         let left = win.left() + self.visual_range + margin;
         let right = win.right() - self.visual_range - margin;
@@ -152,15 +329,29 @@ impl Boid {
         Vec2::ZERO
     }
 
-    pub fn get_neighbours<'a>(&self, flock: &'a Vec<Boid>) -> (Vec<&'a Boid>, Vec<&'a Boid>) {
+    pub fn get_neighbours<'a>(
+        &self,
+        flock: &'a [Boid],
+        grid: &SpatialGrid,
+        neighbourhood: Neighbourhood,
+    ) -> (Vec<&'a Boid>, Vec<&'a Boid>) {
+        let not_self = |i: usize| flock[i] != *self;
+        let candidates = match neighbourhood {
+            Neighbourhood::Metric => grid.within(flock, self.position, self.visual_range, not_self),
+            Neighbourhood::Topological(k) => grid.nearest(flock, self.position, k, None, not_self),
+            Neighbourhood::Hybrid(k) => {
+                grid.nearest(flock, self.position, k, Some(self.visual_range), not_self)
+            }
+        };
+
         let mut nearby_boids: Vec<&Boid> = Vec::new();
         let mut close_boids: Vec<&Boid> = Vec::new();
-        for other in flock {
+        for other in candidates.into_iter().map(|i| &flock[i]) {
             let d = self.position.distance(other.position).abs();
-            if other != self && d <= self.visual_range && d > self.protected_range {
+            if d > self.protected_range {
                 nearby_boids.push(other);
             }
-            if other != self && d < self.protected_range {
+            if d < self.protected_range {
                 close_boids.push(other);
             }
         }
@@ -191,8 +382,8 @@ impl Boid {
         }
     }
 
-    pub fn cursor_interaction(&self, app: &App, cursor_mode: &CursorMode) -> Vec2 {
-        let cursor_pos = app.mouse.position();
+    // `cursor_pos` is in world coordinates, see `Model::cursor`.
+    pub fn cursor_interaction(&self, cursor_pos: Vec2, cursor_mode: &CursorMode) -> Vec2 {
         let (direction, range_modifier) = match cursor_mode {
             CursorMode::Attract => (1.0, 2.0),
             CursorMode::Avoid => (-1.0, 1.0),
@@ -207,33 +398,87 @@ impl Boid {
 
     pub fn update(&mut self) {
         self.velocity += self.acceleration;
+        // A boid at a dead stop has no heading to keep, and clamping it would
+        // give NaN, so it sets off to the right instead.
+        if self.velocity.length_squared() == 0.0 {
+            self.velocity = Vec2::X;
+        }
         // Limit speed between bounds
         self.velocity = self.velocity.clamp_length(self.min_speed, self.max_speed);
 
         self.position += self.velocity;
         // Reset acceleration to 0 each cycle.
         self.acceleration *= 0.0;
+        self.age = self.age.saturating_add(1);
     }
-    pub fn show(&self, draw: &Draw, model: &Model) {
-        // let flock_count = model.flock.len();
-        // let ratio = self.neighbour_count as f32 / flock_count as f32;
-        // let color = get_color(ratio, &model.theme, &self.color);
-        let mut color = self.color;
-        if model.theme == Theme::DeepSea {
-            color = rgb8(200, 200, 200);
+    #[allow(clippy::cast_precision_loss)]
+    fn display_color(&self, model: &Model) -> Rgb8 {
+        let theme = model.theme();
+        if self.leader.is_some() {
+            return theme.leader;
+        }
+        let t = match model.colour_mode {
+            _ if self.b_type == BoidType::Predator => None,
+            ColourMode::Species => None,
+            ColourMode::Speed => {
+                Some((self.velocity.length() - self.min_speed) / (self.max_speed - self.min_speed))
+            }
+            ColourMode::Heading => Some(self.velocity.angle().rem_euclid(TAU) / TAU),
+            ColourMode::Density => Some(self.density / 10.0),
+            ColourMode::Neighbours => Some(self.neighbour_count as f32 / 20.0),
+            // Golden-ratio steps keep neighbouring cluster labels far apart in colour.
+            ColourMode::Cluster => Some((self.cluster as f32 * 0.618_034).fract()),
+            ColourMode::Age => Some(self.age as f32 / 3600.0),
+        };
+        if let Some(t) = t {
+            return model.gradient(model.colour_mode).sample(t);
         }
 
+        match self.b_type {
+            BoidType::Prey => theme.prey,
+            BoidType::Predator => theme.predator,
+        }
+    }
+
+    pub fn show(&self, draw: &Draw, model: &Model) {
+        let color = self.display_color(model);
+        let scale = if self.leader.is_some() { 1.5 } else { 1.0 };
+
         draw.tri()
-            .w_h(self.height, self.width)
+            .w_h(self.height * scale, self.width * scale)
             .xy(self.position)
             .rotate(self.velocity.angle())
             .color(color);
     }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn show_trail(&self, draw: &Draw, model: &Model) {
+        let color = self.display_color(model);
+        let length = self.trail.len() as f32;
+        let mut run: Vec<(Vec2, Rgba)> = Vec::new();
+        // Fade from transparent at the oldest point up to `trail_opacity` at the newest.
+        for (i, point) in self.trail.iter().enumerate() {
+            if let Some(point) = point {
+                let alpha = model.trail_opacity * (i + 1) as f32 / length;
+                run.push((*point, faded(color, alpha)));
+                continue;
+            }
+            if run.len() > 1 {
+                draw.polyline().weight(1.5).points_colored(run.clone());
+            }
+            run.clear();
+        }
+        if run.len() > 1 {
+            draw.polyline().weight(1.5).points_colored(run);
+        }
+    }
 }
 
-pub fn align(nearby_boids: &[&Boid]) -> Vec2 {
+pub fn align(nearby_boids: &[&Boid], leader_weight: f32) -> Vec2 {
     nearby_boids
         .iter()
-        .fold(Vec2::ZERO, |sum, &boid| sum + boid.velocity)
+        .fold(Vec2::ZERO, |sum, &boid| {
+            sum + boid.velocity * boid.influence(leader_weight)
+        })
         .normalize_or_zero()
 }
@@ -0,0 +1,134 @@
+use std::ops::RangeInclusive;
+
+use nannou::prelude::*;
+
+pub const ZOOM_RANGE: RangeInclusive<f32> = 0.1..=10.0;
+// The world never shrinks below this many units across.
+const MIN_WORLD: f32 = 100.0;
+
+// What happens to the view when the window changes size.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ResizeMode {
+    // The same world fills the window, drawn bigger or smaller.
+    Rescale,
+    // Nothing moves or scales; the window just shows more or less of the world.
+    Crop,
+    // The world grows or shrinks with the window, keeping boids the same size on screen.
+    Extend,
+}
+
+impl ResizeMode {
+    pub fn next(&self) -> ResizeMode {
+        match *self {
+            ResizeMode::Rescale => ResizeMode::Crop,
+            ResizeMode::Crop => ResizeMode::Extend,
+            ResizeMode::Extend => ResizeMode::Rescale,
+        }
+    }
+}
+
+// Which part of the world is on screen. The simulation works in world units,
+// and screen positions are nannou's window coordinates, which are already in
+// logical points so HiDPI displays don't change anything.
+pub struct Camera {
+    // The world position shown in the middle of the window.
+    pub centre: Vec2,
+    // Points per world unit before zooming, set so the world fits the window.
+    pub scale: f32,
+    pub zoom: f32,
+    // The window size the scale was last worked out for.
+    pub viewport: Vec2,
+    drag_from: Option<Vec2>,
+}
+
+impl Camera {
+    // A camera showing all of `world` in `window`.
+    pub fn fit(world: Rect, window: Rect) -> Camera {
+        let mut camera = Camera {
+            centre: Vec2::ZERO,
+            scale: 1.0,
+            zoom: 1.0,
+            viewport: window.wh(),
+            drag_from: None,
+        };
+        camera.reset(world, window);
+        camera
+    }
+
+    pub fn points_per_unit(&self) -> f32 {
+        self.scale * self.zoom
+    }
+
+    pub fn to_world(&self, screen: Vec2) -> Vec2 {
+        self.centre + screen / self.points_per_unit()
+    }
+
+    // A `Draw` that takes world positions, for everything that lives in the simulation.
+    pub fn transform(&self, draw: &Draw) -> Draw {
+        draw.scale(self.points_per_unit()).xy(-self.centre)
+    }
+
+    // Zooms by `factor`, keeping the world position under `screen` where it is.
+    pub fn zoom_at(&mut self, screen: Vec2, factor: f32) {
+        let anchor = self.to_world(screen);
+        self.zoom = (self.zoom * factor).clamp(*ZOOM_RANGE.start(), *ZOOM_RANGE.end());
+        self.centre = anchor - screen / self.points_per_unit();
+    }
+
+    // Moves the view by a distance measured in screen points.
+    pub fn pan(&mut self, screen_delta: Vec2) {
+        self.centre += screen_delta / self.points_per_unit();
+    }
+
+    pub fn start_drag(&mut self, screen: Vec2) {
+        self.drag_from = Some(screen);
+    }
+
+    // Follows the mouse while the button that started a drag is held.
+    pub fn drag(&mut self, screen: Vec2, held: bool) {
+        match self.drag_from {
+            Some(from) if held => {
+                self.pan(from - screen);
+                self.drag_from = Some(screen);
+            }
+            _ => self.drag_from = None,
+        }
+    }
+
+    // Keeps the middle of the view inside the world, so it can't be lost off the edge.
+    pub fn clamp_to(&mut self, world: Rect) {
+        self.centre = self.centre.clamp(world.bottom_left(), world.top_right());
+    }
+
+    // Fits the whole world into `window`, undoing any panning and zooming.
+    pub fn reset(&mut self, world: Rect, window: Rect) {
+        self.centre = world.xy();
+        self.zoom = 1.0;
+        self.scale = fit_scale(world.wh(), window.wh());
+        self.viewport = window.wh();
+    }
+
+    // Reacts to the window becoming `size`, returning the new world for `Extend`.
+    pub fn resize(&mut self, size: Vec2, world: Rect, mode: ResizeMode) -> Rect {
+        // Minimising reports a zero size, which there's nothing sensible to fit to.
+        if size.min_element() <= 0.0 {
+            return world;
+        }
+        let grown = (size - self.viewport) / self.points_per_unit();
+        self.viewport = size;
+        match mode {
+            ResizeMode::Rescale => {
+                self.scale = fit_scale(world.wh(), size);
+                world
+            }
+            ResizeMode::Crop => world,
+            ResizeMode::Extend => {
+                Rect::from_xy_wh(world.xy(), (world.wh() + grown).max(Vec2::splat(MIN_WORLD)))
+            }
+        }
+    }
+}
+
+fn fit_scale(world: Vec2, window: Vec2) -> f32 {
+    (window.x / world.x).min(window.y / world.y)
+}
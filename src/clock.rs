@@ -0,0 +1,66 @@
+use std::ops::RangeInclusive;
+
+pub const TIME_SCALE_RANGE: RangeInclusive<f32> = 0.1..=10.0;
+// The speeds the keyboard steps through.
+const TIME_SCALES: [f32; 7] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0];
+
+// Decides how many simulation steps each frame gets. Faster than real time runs
+// several steps a frame, slower runs one every few frames.
+pub struct Clock {
+    pub paused: bool,
+    pub time_scale: f32,
+    queued: u32,
+    // Fractions of a step carried over between frames.
+    carry: f32,
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            paused: false,
+            time_scale: 1.0,
+            queued: 0,
+            carry: 0.0,
+        }
+    }
+
+    // Pauses if need be, and runs exactly one more step.
+    pub fn step_once(&mut self) {
+        self.paused = true;
+        self.queued += 1;
+    }
+
+    pub fn faster(&mut self) {
+        if let Some(scale) = TIME_SCALES.iter().find(|s| **s > self.time_scale) {
+            self.time_scale = *scale;
+        }
+    }
+
+    pub fn slower(&mut self) {
+        if let Some(scale) = TIME_SCALES.iter().rev().find(|s| **s < self.time_scale) {
+            self.time_scale = *scale;
+        }
+    }
+
+    // How many steps to run this frame.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn steps(&mut self) -> u32 {
+        if self.paused {
+            self.carry = 0.0;
+            return std::mem::take(&mut self.queued);
+        }
+        self.queued = 0;
+        self.carry += self
+            .time_scale
+            .clamp(*TIME_SCALE_RANGE.start(), *TIME_SCALE_RANGE.end());
+        let steps = self.carry.floor();
+        self.carry -= steps;
+        steps as u32
+    }
+}
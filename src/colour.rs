@@ -0,0 +1,173 @@
+use nannou::prelude::*;
+
+use crate::model::Model;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ColourMode {
+    Species,
+    Speed,
+    Heading,
+    Density,
+    Neighbours,
+    Cluster,
+    Age,
+}
+
+impl ColourMode {
+    pub const ALL: [ColourMode; 7] = [
+        ColourMode::Species,
+        ColourMode::Speed,
+        ColourMode::Heading,
+        ColourMode::Density,
+        ColourMode::Neighbours,
+        ColourMode::Cluster,
+        ColourMode::Age,
+    ];
+
+    pub fn next(&self) -> ColourMode {
+        match *self {
+            ColourMode::Species => ColourMode::Speed,
+            ColourMode::Speed => ColourMode::Heading,
+            ColourMode::Heading => ColourMode::Density,
+            ColourMode::Density => ColourMode::Neighbours,
+            ColourMode::Neighbours => ColourMode::Cluster,
+            ColourMode::Cluster => ColourMode::Age,
+            ColourMode::Age => ColourMode::Species,
+        }
+    }
+
+    // What the two ends of the legend mean.
+    pub fn range_labels(self) -> (&'static str, &'static str) {
+        match self {
+            ColourMode::Species => ("", ""),
+            ColourMode::Speed => ("slow", "fast"),
+            ColourMode::Heading => ("0°", "360°"),
+            ColourMode::Density => ("sparse", "dense"),
+            ColourMode::Neighbours => ("0", "20+"),
+            ColourMode::Cluster => ("by cluster", ""),
+            ColourMode::Age => ("new", "60s+"),
+        }
+    }
+
+    pub fn default_gradient(self) -> Gradient {
+        match self {
+            ColourMode::Heading | ColourMode::Cluster => Gradient::rainbow(),
+            ColourMode::Speed | ColourMode::Density => Gradient::heat(),
+            ColourMode::Species | ColourMode::Neighbours | ColourMode::Age => Gradient::ocean(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Gradient {
+    pub stops: Vec<Rgb8>,
+}
+
+impl Gradient {
+    pub fn heat() -> Gradient {
+        Gradient {
+            stops: vec![
+                rgb8(20, 20, 80),
+                rgb8(180, 30, 60),
+                rgb8(250, 140, 20),
+                rgb8(250, 240, 120),
+            ],
+        }
+    }
+
+    pub fn ocean() -> Gradient {
+        Gradient {
+            stops: vec![rgb8(10, 30, 70), rgb8(20, 120, 160), rgb8(150, 230, 200)],
+        }
+    }
+
+    pub fn greyscale() -> Gradient {
+        Gradient {
+            stops: vec![rgb8(20, 20, 20), rgb8(230, 230, 230)],
+        }
+    }
+
+    // Starts and ends on the same colour so it works for angles.
+    pub fn rainbow() -> Gradient {
+        Gradient {
+            stops: vec![
+                rgb8(230, 40, 40),
+                rgb8(230, 200, 40),
+                rgb8(40, 200, 60),
+                rgb8(40, 180, 230),
+                rgb8(150, 60, 220),
+                rgb8(230, 40, 40),
+            ],
+        }
+    }
+
+    pub fn presets() -> [Gradient; 4] {
+        [
+            Gradient::heat(),
+            Gradient::ocean(),
+            Gradient::greyscale(),
+            Gradient::rainbow(),
+        ]
+    }
+
+    pub fn next(&self) -> Gradient {
+        let presets = Gradient::presets();
+        let i = presets.iter().position(|g| g == self).unwrap_or(0);
+        presets[(i + 1) % presets.len()].clone()
+    }
+
+    // Linear blend between the stops either side of `t`, which is clamped to 0..=1.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn sample(&self, t: f32) -> Rgb8 {
+        let Some(last) = self.stops.len().checked_sub(1) else {
+            return BLACK;
+        };
+        let position = t.clamp(0.0, 1.0) * last as f32;
+        let i = (position.floor() as usize).min(last.saturating_sub(1));
+        let (from, to) = (self.stops[i], self.stops[(i + 1).min(last)]);
+        let blend = position - i as f32;
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * blend) as u8;
+        rgb8(
+            mix(from.red, to.red),
+            mix(from.green, to.green),
+            mix(from.blue, to.blue),
+        )
+    }
+}
+
+pub fn draw_colour_legend(app: &App, model: &Model, draw: &Draw) {
+    if !model.show_text || model.colour_mode == ColourMode::Species {
+        return;
+    }
+    let gradient = model.gradient(model.colour_mode);
+    let bottom_right = app.window_rect().pad(30.0).bottom_right();
+    let (width, height) = (150.0, 10.0);
+    let start = bottom_right - vec2(width, 0.0);
+    let steps = 30;
+    for step in 0..steps {
+        #[allow(clippy::cast_precision_loss)]
+        let t = step as f32 / (steps - 1) as f32;
+        draw.rect()
+            .x_y(start.x + t * width, bottom_right.y)
+            .w_h(width / steps as f32 + 1.0, height)
+            .color(gradient.sample(t));
+    }
+    let (low, high) = model.colour_mode.range_labels();
+    let label_y = bottom_right.y + height * 1.5;
+    let text = model.theme().text;
+    draw.text(&format!("{:?}", model.colour_mode))
+        .x_y(start.x + width / 2.0, label_y + 14.0)
+        .color(text);
+    draw.text(low)
+        .x_y(start.x, label_y)
+        .font_size(10)
+        .color(text);
+    draw.text(high)
+        .x_y(bottom_right.x, label_y)
+        .font_size(10)
+        .color(text);
+}
@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 #[allow(clippy::module_name_repetitions)]
 pub enum CursorMode {
     Attract,
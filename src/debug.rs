@@ -0,0 +1,121 @@
+use nannou::prelude::*;
+
+use crate::{boids::Boid, model::Model, simulation::Simulation, theme::faded};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DebugOverlay {
+    Off,
+    Forces,
+    Full,
+}
+
+impl DebugOverlay {
+    pub fn next(&self) -> DebugOverlay {
+        match *self {
+            DebugOverlay::Off => DebugOverlay::Forces,
+            DebugOverlay::Forces => DebugOverlay::Full,
+            DebugOverlay::Full => DebugOverlay::Off,
+        }
+    }
+}
+
+// Behaviours are coloured by their place in the registry, going round again
+// if there are more of them than colours.
+const FORCE_COLOURS: [Rgb8; 8] = [BLUE, RED, GREEN, DARKVIOLET, DEEPPINK, ORANGE, GOLD, TEAL];
+// Steering forces are tiny next to velocities, so they need a bigger scale to be visible.
+const FORCE_SCALE: f32 = 40.0;
+const VELOCITY_SCALE: f32 = 8.0;
+
+// Draws `sim`'s grid, forces and ranges through `draw`, which takes world positions.
+pub fn draw_debug_overlay(model: &Model, sim: &Simulation, draw: &Draw) {
+    if model.debug_overlay == DebugOverlay::Off {
+        return;
+    }
+    for cell in sim.grid.cells() {
+        draw.rect()
+            .xy(cell.xy())
+            .wh(cell.wh())
+            .no_fill()
+            .stroke_weight(1.0)
+            .stroke(faded(model.theme().overlay, 0.1));
+    }
+    for boid in sim.flock.iter().chain(&sim.predators) {
+        if model.debug_overlay == DebugOverlay::Full {
+            draw_ranges(model, sim, boid, draw);
+        }
+        draw_vectors(model, boid, draw);
+    }
+}
+
+fn draw_vectors(model: &Model, boid: &Boid, draw: &Draw) {
+    let theme = model.theme();
+    let arrow = |vector: Vec2, scale: f32, colour: Rgba| {
+        if vector != Vec2::ZERO {
+            draw.line()
+                .start(boid.position)
+                .end(boid.position + vector * scale)
+                .weight(1.0)
+                .color(colour);
+        }
+    };
+    arrow(boid.velocity, VELOCITY_SCALE, faded(theme.overlay, 0.5));
+    for (name, force) in boid.forces.named() {
+        arrow(*force, FORCE_SCALE, faded(force_colour(model, name), 0.8));
+    }
+    arrow(boid.forces.total(), FORCE_SCALE, faded(theme.text, 0.9));
+}
+
+fn draw_ranges(model: &Model, sim: &Simulation, boid: &Boid, draw: &Draw) {
+    draw.ellipse()
+        .xy(boid.position)
+        .radius(boid.visual_range)
+        .no_fill()
+        .stroke_weight(1.0)
+        .stroke(faded(model.theme().overlay, 0.15));
+    draw.ellipse()
+        .xy(boid.position)
+        .radius(boid.protected_range)
+        .no_fill()
+        .stroke_weight(1.0)
+        .stroke(faded(model.theme().predator, 0.15));
+
+    let options = sim.options(boid.b_type);
+    let (nearby_boids, close_boids) =
+        boid.get_neighbours(&sim.flock, &sim.grid, options.neighbourhood);
+    for neighbour in nearby_boids.iter().chain(&close_boids) {
+        draw.line()
+            .start(boid.position)
+            .end(neighbour.position)
+            .weight(0.5)
+            .color(faded(model.theme().overlay, 0.15));
+    }
+}
+
+// Which colour is which force, drawn once in screen space however many halves there are.
+pub fn draw_debug_legend(app: &App, model: &Model, draw: &Draw) {
+    if model.debug_overlay == DebugOverlay::Off {
+        return;
+    }
+    let top_right = app.window_rect().pad(20.0).top_right();
+    for (i, name) in model.registry.names().into_iter().enumerate() {
+        let colour = force_colour(model, name);
+        #[allow(clippy::cast_precision_loss)]
+        let y = top_right.y - i as f32 * 14.0;
+        draw.line()
+            .start(vec2(top_right.x - 100.0, y))
+            .end(vec2(top_right.x - 80.0, y))
+            .weight(3.0)
+            .color(colour);
+        draw.text(name)
+            .x_y(top_right.x - 30.0, y)
+            .w(90.0)
+            .left_justify()
+            .font_size(11)
+            .color(model.theme().text);
+    }
+}
+
+fn force_colour(model: &Model, name: &str) -> Rgb8 {
+    let index = model.registry.names().iter().position(|n| *n == name);
+    FORCE_COLOURS[index.unwrap_or(0) % FORCE_COLOURS.len()]
+}
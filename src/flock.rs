@@ -1,18 +1,47 @@
-use nannou::prelude::*;
+use nannou::{prelude::*, rand::Rng};
 
-use crate::boids::{Boid, BoidType};
+use crate::{boids::Boid, model::BoidOptions, spatial::SpatialGrid};
 
 pub struct Flock;
 impl Flock {
-    pub fn new_flock(bounds: Rect, n_boids: usize, boid_type: BoidType) -> Vec<Boid> {
-        let (left, right, bottom, top) = bounds.l_r_b_t();
-        let mut flock: Vec<Boid> = Vec::new();
-        for _ in 0..n_boids {
-            let x_pos = random_range(left, right);
-            let y_pos = random_range(bottom, top);
-            let boid = Boid::new(x_pos, y_pos, boid_type);
-            flock.push(boid);
-        }
+    pub fn new_flock(bounds: Rect, options: &BoidOptions, rng: &mut impl Rng) -> Vec<Boid> {
+        (0..options.flock_size)
+            .map(|_| options.spawn(bounds, rng))
+            .collect()
+    }
+
+    // How crowded each boid's visual range is, with closer flockmates counting for more.
+    pub fn local_density(flock: &[Boid], grid: &SpatialGrid) -> Vec<f32> {
         flock
+            .iter()
+            .enumerate()
+            .map(|(i, boid)| {
+                grid.within(flock, boid.position, boid.visual_range, |j| j != i)
+                    .into_iter()
+                    .map(|j| 1.0 - boid.position.distance(flock[j].position) / boid.visual_range)
+                    .sum()
+            })
+            .collect()
+    }
+
+    // Connected groups of boids that can see each other, labelled by their lowest
+    // index so labels stay put from frame to frame.
+    pub fn clusters(flock: &[Boid], grid: &SpatialGrid) -> Vec<usize> {
+        fn root(parents: &mut [usize], mut i: usize) -> usize {
+            while parents[i] != i {
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            i
+        }
+
+        let mut parents: Vec<usize> = (0..flock.len()).collect();
+        for (i, boid) in flock.iter().enumerate() {
+            for j in grid.within(flock, boid.position, boid.visual_range, |j| j > i) {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+        (0..flock.len()).map(|i| root(&mut parents, i)).collect()
     }
 }
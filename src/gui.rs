@@ -0,0 +1,499 @@
+use std::{collections::HashSet, ops::RangeInclusive};
+
+use nannou::prelude::*;
+
+use crate::theme::{faded, Theme};
+
+const PANEL_WIDTH: f32 = 290.0;
+const ROW_HEIGHT: f32 = 20.0;
+const ROW_GAP: f32 = 4.0;
+const LABEL_WIDTH: f32 = 120.0;
+const VALUE_WIDTH: f32 = 50.0;
+// How much of a text field's contents fits in its box, from the end.
+const TEXT_FIELD_CHARS: usize = 24;
+
+// A small immediate-mode control panel. Nannou only gives `view` a shared
+// reference to the model, so widgets are laid out and handle input during
+// `update`, leaving behind a list of items for `view` to draw.
+pub struct Gui {
+    pub open: bool,
+    open_sections: HashSet<&'static str>,
+    // Widgets are identified by their position in the layout, which is stable between frames.
+    dragging: Option<usize>,
+    editing: Option<(usize, String)>,
+    typed: Vec<char>,
+    was_down: bool,
+    wheel: f32,
+    scroll: f32,
+    rect: Rect,
+    items: Vec<Item>,
+}
+
+enum Item {
+    Header {
+        rect: Rect,
+        label: String,
+        open: bool,
+    },
+    Slider {
+        rect: Rect,
+        label: String,
+        fraction: f32,
+        value: String,
+        editing: bool,
+    },
+    Toggle {
+        rect: Rect,
+        label: String,
+        on: bool,
+    },
+    Button {
+        rect: Rect,
+        label: String,
+    },
+    TextField {
+        rect: Rect,
+        label: String,
+        text: String,
+        editing: bool,
+    },
+}
+
+// Where typing into a field has got to this frame.
+enum Edit {
+    Idle,
+    Typing,
+    Done(String),
+}
+
+impl Gui {
+    pub fn new(open_sections: &[&'static str]) -> Gui {
+        Gui {
+            open: false,
+            open_sections: open_sections.iter().copied().collect(),
+            dragging: None,
+            editing: None,
+            typed: Vec::new(),
+            was_down: false,
+            wheel: 0.0,
+            scroll: 0.0,
+            rect: Rect::from_w_h(0.0, 0.0),
+            items: Vec::new(),
+        }
+    }
+
+    // Whether the panel is under `position`, so clicks there don't fall through to the flock.
+    pub fn contains(&self, position: Vec2) -> bool {
+        self.rect.contains(position)
+    }
+
+    // Keyboard shortcuts are ignored while anything is being typed into a field.
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    pub fn type_character(&mut self, character: char) {
+        if self.editing.is_some() {
+            self.typed.push(character);
+        }
+    }
+
+    pub fn scroll(&mut self, delta: f32) {
+        self.wheel += delta;
+    }
+
+    // Starts a layout pass for this frame, pinned to the top right of `window`.
+    pub fn begin<'a>(&'a mut self, app: &App, window: Rect) -> Layout<'a> {
+        let mouse = app.mouse.position();
+        let down = app.mouse.buttons.left().is_down();
+        let clicked = down && !self.was_down;
+        self.was_down = down;
+        if !down {
+            self.dragging = None;
+        }
+        let wheel = std::mem::take(&mut self.wheel);
+        app.set_exit_on_escape(self.editing.is_none());
+        self.items.clear();
+        let top_left = window.pad(10.0).top_right() - vec2(PANEL_WIDTH, -self.scroll);
+        Layout {
+            gui: self,
+            mouse,
+            down,
+            clicked,
+            wheel,
+            top_left,
+            cursor: top_left,
+            window,
+        }
+    }
+
+    pub fn draw(&self, draw: &Draw, theme: &Theme) {
+        if self.items.is_empty() {
+            return;
+        }
+        draw.rect()
+            .xy(self.rect.xy())
+            .wh(self.rect.wh())
+            .color(faded(theme.background, 0.9))
+            .stroke_weight(1.0)
+            .stroke(faded(theme.text, 0.4));
+
+        let label = |text: &str, rect: Rect| {
+            draw.text(text)
+                .xy(rect.xy())
+                .wh(rect.wh())
+                .left_justify()
+                .align_text_middle_y()
+                .font_size(12)
+                .color(theme.text);
+        };
+        for item in &self.items {
+            match item {
+                Item::Header {
+                    rect,
+                    label: text,
+                    open,
+                } => {
+                    draw.rect()
+                        .xy(rect.xy())
+                        .wh(rect.wh())
+                        .color(faded(theme.text, 0.15));
+                    let arrow = if *open { "v" } else { ">" };
+                    label(&format!("{arrow} {text}"), rect.pad_left(6.0));
+                }
+                Item::Slider {
+                    rect,
+                    label: text,
+                    fraction,
+                    value,
+                    editing,
+                } => {
+                    let (label_rect, track, value_rect) = slider_parts(*rect);
+                    label(text, label_rect);
+                    draw.rect()
+                        .xy(track.xy())
+                        .wh(track.wh())
+                        .color(faded(theme.text, 0.15));
+                    let filled = track.pad_right(track.w() * (1.0 - fraction));
+                    draw.rect()
+                        .xy(filled.xy())
+                        .wh(filled.wh())
+                        .color(faded(theme.overlay, 0.6));
+                    draw.rect()
+                        .xy(value_rect.xy())
+                        .wh(value_rect.wh())
+                        .no_fill()
+                        .stroke_weight(1.0)
+                        .stroke(faded(theme.text, if *editing { 0.9 } else { 0.3 }));
+                    let value = if *editing {
+                        format!("{value}|")
+                    } else {
+                        value.clone()
+                    };
+                    label(&value, value_rect.pad_left(4.0));
+                }
+                Item::Toggle {
+                    rect,
+                    label: text,
+                    on,
+                } => {
+                    let (label_rect, track, _) = slider_parts(*rect);
+                    label(text, label_rect);
+                    let r#box = Rect::from_xy_wh(
+                        track.mid_left() + vec2(track.h() / 2.0, 0.0),
+                        vec2(track.h(), track.h()),
+                    );
+                    draw.rect()
+                        .xy(r#box.xy())
+                        .wh(r#box.wh())
+                        .color(faded(theme.overlay, if *on { 0.8 } else { 0.0 }))
+                        .stroke_weight(1.0)
+                        .stroke(faded(theme.text, 0.5));
+                }
+                Item::Button { rect, label: text } => {
+                    draw.rect()
+                        .xy(rect.xy())
+                        .wh(rect.wh())
+                        .color(faded(theme.text, 0.08))
+                        .stroke_weight(1.0)
+                        .stroke(faded(theme.text, 0.3));
+                    label(text, rect.pad_left(6.0));
+                }
+                Item::TextField {
+                    rect,
+                    label: text,
+                    text: typed,
+                    editing,
+                } => {
+                    let (label_rect, field) = text_field_parts(*rect);
+                    label(text, label_rect);
+                    draw.rect()
+                        .xy(field.xy())
+                        .wh(field.wh())
+                        .no_fill()
+                        .stroke_weight(1.0)
+                        .stroke(faded(theme.text, if *editing { 0.9 } else { 0.3 }));
+                    let shown = if *editing {
+                        format!("{typed}|")
+                    } else {
+                        typed.clone()
+                    };
+                    label(&shown, field.pad_left(4.0));
+                }
+            }
+        }
+    }
+}
+
+// Label, slider track and numeric field, left to right.
+fn slider_parts(rect: Rect) -> (Rect, Rect, Rect) {
+    let label = Rect::from_corners(rect.bottom_left(), rect.top_left() + vec2(LABEL_WIDTH, 0.0));
+    let value = Rect::from_corners(
+        rect.bottom_right() - vec2(VALUE_WIDTH, 0.0),
+        rect.top_right(),
+    );
+    let track = Rect::from_corners(label.bottom_right(), value.top_left())
+        .pad_left(4.0)
+        .pad_right(8.0)
+        .pad_top(5.0)
+        .pad_bottom(5.0);
+    (label, track, value)
+}
+
+// Label and a text box taking up the rest of the row.
+fn text_field_parts(rect: Rect) -> (Rect, Rect) {
+    let label = Rect::from_corners(rect.bottom_left(), rect.top_left() + vec2(LABEL_WIDTH, 0.0));
+    let field = Rect::from_corners(label.bottom_right(), rect.top_right()).pad_left(4.0);
+    (label, field)
+}
+
+pub struct Layout<'a> {
+    gui: &'a mut Gui,
+    mouse: Vec2,
+    down: bool,
+    clicked: bool,
+    wheel: f32,
+    top_left: Vec2,
+    cursor: Vec2,
+    window: Rect,
+}
+
+impl Layout<'_> {
+    fn row(&mut self) -> Rect {
+        let rect = Rect::from_corners(
+            self.cursor - vec2(0.0, ROW_HEIGHT),
+            self.cursor + vec2(PANEL_WIDTH, 0.0),
+        );
+        self.cursor.y -= ROW_HEIGHT + ROW_GAP;
+        rect
+    }
+
+    fn clicked_in(&self, rect: Rect) -> bool {
+        self.clicked && rect.contains(self.mouse)
+    }
+
+    // The panel's own title bar. Everything else is skipped while it's collapsed.
+    pub fn title(&mut self, label: &str) -> bool {
+        let rect = self.row();
+        if self.clicked_in(rect) {
+            self.gui.open = !self.gui.open;
+        }
+        self.gui.items.push(Item::Header {
+            rect,
+            label: label.to_string(),
+            open: self.gui.open,
+        });
+        self.gui.open
+    }
+
+    // A collapsible section header, returning whether its contents should be shown.
+    pub fn section(&mut self, label: &'static str) -> bool {
+        let rect = self.row();
+        if self.clicked_in(rect) && !self.gui.open_sections.remove(label) {
+            self.gui.open_sections.insert(label);
+        }
+        let open = self.gui.open_sections.contains(label);
+        self.gui.items.push(Item::Header {
+            rect,
+            label: label.to_string(),
+            open,
+        });
+        open
+    }
+
+    pub fn slider(&mut self, label: &str, value: &mut f32, range: RangeInclusive<f32>) {
+        let id = self.gui.items.len();
+        let rect = self.row();
+        let (_, track, value_rect) = slider_parts(rect);
+        let (min, max) = (*range.start(), *range.end());
+
+        if self.clicked_in(track) {
+            self.gui.dragging = Some(id);
+        }
+        if self.gui.dragging == Some(id) && self.down {
+            let fraction = ((self.mouse.x - track.left()) / track.w()).clamp(0.0, 1.0);
+            *value = min + fraction * (max - min);
+        }
+        if self.wheel != 0.0 && rect.contains(self.mouse) {
+            *value += self.wheel * (max - min) / 100.0;
+            self.wheel = 0.0;
+        }
+
+        let editing = self.edit_field(id, value, value_rect);
+        *value = value.clamp(min, max);
+        let text = match &self.gui.editing {
+            Some((editing_id, text)) if *editing_id == id => text.clone(),
+            _ => format_value(*value),
+        };
+        self.gui.items.push(Item::Slider {
+            rect,
+            label: label.to_string(),
+            fraction: (*value - min) / (max - min),
+            value: text,
+            editing,
+        });
+    }
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn slider_usize(&mut self, label: &str, value: &mut usize, range: RangeInclusive<usize>) {
+        let mut as_float = *value as f32;
+        self.slider(
+            label,
+            &mut as_float,
+            *range.start() as f32..=*range.end() as f32,
+        );
+        *value = as_float.round() as usize;
+    }
+
+    // A box to type a line of text into, like a preset code. Returns the text
+    // once Enter is pressed.
+    pub fn text_field(&mut self, label: &str, placeholder: &str) -> Option<String> {
+        let id = self.gui.items.len();
+        let rect = self.row();
+        let (_, field) = text_field_parts(rect);
+        let (editing, entered) = match self.edit(id, field, |c| !c.is_control()) {
+            Edit::Idle => (false, None),
+            Edit::Typing => (true, None),
+            Edit::Done(text) => (false, Some(text)),
+        };
+        let text = match &self.gui.editing {
+            Some((editing_id, text)) if *editing_id == id => {
+                // Long text is shown from the end, where the typing is.
+                let skip = text.chars().count().saturating_sub(TEXT_FIELD_CHARS);
+                text.chars().skip(skip).collect()
+            }
+            _ => placeholder.to_string(),
+        };
+        self.gui.items.push(Item::TextField {
+            rect,
+            label: label.to_string(),
+            text,
+            editing,
+        });
+        entered.filter(|text| !text.trim().is_empty())
+    }
+
+    // Clicking a value starts typing into it; Enter or clicking away applies it,
+    // Escape throws it away.
+    fn edit_field(&mut self, id: usize, value: &mut f32, rect: Rect) -> bool {
+        match self.edit(id, rect, |c| c.is_ascii_digit() || c == '.' || c == '-') {
+            Edit::Idle => false,
+            Edit::Typing => true,
+            Edit::Done(text) => {
+                if let Ok(typed) = text.parse::<f32>() {
+                    *value = typed;
+                }
+                false
+            }
+        }
+    }
+
+    // Typing into the field `id` at `rect`, keeping only the characters `allowed`.
+    fn edit(&mut self, id: usize, rect: Rect, allowed: impl Fn(char) -> bool) -> Edit {
+        if self.clicked_in(rect) && self.gui.editing.is_none() {
+            self.gui.editing = Some((id, String::new()));
+            self.gui.typed.clear();
+            return Edit::Typing;
+        }
+        let Some((editing_id, mut text)) = self.gui.editing.take() else {
+            return Edit::Idle;
+        };
+        if editing_id != id {
+            self.gui.editing = Some((editing_id, text));
+            return Edit::Idle;
+        }
+
+        let mut finished = self.clicked && !rect.contains(self.mouse);
+        let mut cancelled = false;
+        for character in self.gui.typed.drain(..) {
+            match character {
+                '\r' | '\n' => finished = true,
+                '\u{1b}' => cancelled = true,
+                '\u{8}' => {
+                    text.pop();
+                }
+                c if allowed(c) => text.push(c),
+                _ => {}
+            }
+        }
+        if cancelled {
+            return Edit::Idle;
+        }
+        if finished {
+            return Edit::Done(text);
+        }
+        self.gui.editing = Some((editing_id, text));
+        Edit::Typing
+    }
+
+    pub fn toggle(&mut self, label: &str, on: &mut bool) {
+        let rect = self.row();
+        if self.clicked_in(rect) {
+            *on = !*on;
+        }
+        self.gui.items.push(Item::Toggle {
+            rect,
+            label: label.to_string(),
+            on: *on,
+        });
+    }
+
+    pub fn button(&mut self, label: String) -> bool {
+        let rect = self.row();
+        let clicked = self.clicked_in(rect);
+        self.gui.items.push(Item::Button { rect, label });
+        clicked
+    }
+}
+
+impl Drop for Layout<'_> {
+    // Works out the panel's final size once every widget has been laid out.
+    fn drop(&mut self) {
+        let bottom = self.cursor.y + ROW_GAP;
+        self.gui.rect = Rect::from_corners(
+            vec2(self.top_left.x, bottom),
+            self.top_left + vec2(PANEL_WIDTH, 0.0),
+        )
+        .pad(-4.0);
+
+        // A wheel over the panel that no slider took scrolls the panel, but only
+        // as far as its contents overflow the window.
+        if self.gui.contains(self.mouse) {
+            self.gui.scroll -= self.wheel * ROW_HEIGHT;
+        }
+        let overflow = (self.top_left.y - bottom) - (self.window.h() - 20.0);
+        self.gui.scroll = self.gui.scroll.clamp(0.0, overflow.max(0.0));
+    }
+}
+
+fn format_value(value: f32) -> String {
+    if value.fract() == 0.0 {
+        format!("{value:.0}")
+    } else {
+        format!("{value:.2}")
+    }
+}
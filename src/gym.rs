@@ -0,0 +1,380 @@
+use nannou::prelude::*;
+
+use crate::{
+    boids::{
+        Boid,
+        BoidType::{self, Predator, Prey},
+    },
+    model::{
+        arg, preset_from_args, registry, seed_from_args, world_from_args, BoidOptions,
+        DEFAULT_WORLD_SIZE,
+    },
+    simulation::{Environment, Simulation},
+};
+
+// Values per boid seen: position and velocity relative to the agent, then 1.0
+// if there's a boid in that slot at all, or zeros if there are too few.
+const PER_BOID: usize = 5;
+// The agent's own velocity and its distance to each wall.
+const OWN_VALUES: usize = 6;
+
+// A boid steered by a learning agent instead of the flocking rules.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Agent {
+    pub boid_type: BoidType,
+    pub index: usize,
+}
+
+// What happened to one agent during a step.
+pub struct Outcome {
+    // Prey this agent caught, if it's a predator.
+    pub catches: usize,
+    // Whether this agent was caught, if it's prey.
+    pub caught: bool,
+}
+
+// Turns what happened to an agent into a reward. Every hook an environment
+// has is added up.
+pub trait Reward: Send + Sync {
+    fn reward(&self, agent: &Agent, outcome: &Outcome, sim: &Simulation) -> f32;
+}
+
+// Pays predators for each prey they catch.
+pub struct Catches(pub f32);
+
+// Pays prey for every step they aren't caught, and `caught` (usually
+// negative) when they are.
+pub struct Survival {
+    pub per_step: f32,
+    pub caught: f32,
+}
+
+impl Reward for Catches {
+    #[allow(clippy::cast_precision_loss)]
+    fn reward(&self, agent: &Agent, outcome: &Outcome, _sim: &Simulation) -> f32 {
+        match agent.boid_type {
+            Predator => outcome.catches as f32 * self.0,
+            Prey => 0.0,
+        }
+    }
+}
+
+impl Reward for Survival {
+    fn reward(&self, agent: &Agent, outcome: &Outcome, _sim: &Simulation) -> f32 {
+        match agent.boid_type {
+            Prey if outcome.caught => self.caught,
+            Prey => self.per_step,
+            Predator => 0.0,
+        }
+    }
+}
+
+pub struct Config {
+    pub world: Rect,
+    // The first episode's seed. Each reset without a seed moves on to the next.
+    pub seed: u64,
+    pub agents: Vec<Agent>,
+    // How many of the nearest prey, and of the nearest predators, an agent sees.
+    pub neighbours: usize,
+    // Episodes are cut short after this many steps.
+    pub max_steps: usize,
+}
+
+// The result of one step, with one entry per agent.
+pub struct Step {
+    pub observations: Vec<Vec<f32>>,
+    pub rewards: Vec<f32>,
+    // Every controlled prey has been caught.
+    pub terminated: bool,
+    // The episode ran out of steps.
+    pub truncated: bool,
+}
+
+// A gym-style wrapper around a headless simulation: `reset` starts an
+// episode, then `step` takes one action per agent and hands back what each
+// agent sees next and the reward it earned. Boids that aren't agents flock
+// as usual.
+pub struct Gym {
+    pub sim: Simulation,
+    pub env: Environment,
+    pub config: Config,
+    pub rewards: Vec<Box<dyn Reward>>,
+    next_seed: u64,
+    steps: usize,
+    // Controlled prey caught so far this episode.
+    caught: Vec<bool>,
+}
+
+impl Gym {
+    // Species with fewer boids than agents are topped up.
+    pub fn new(mut sim: Simulation, config: Config) -> Gym {
+        for agent in &config.agents {
+            let options = match agent.boid_type {
+                Prey => &mut sim.boid_options,
+                Predator => &mut sim.predator_options,
+            };
+            options.flock_size = options.flock_size.max(agent.index + 1);
+        }
+        // Spawned straight away, so every agent exists even if `step` comes before `reset`.
+        sim.restart(config.seed, config.world);
+        sim.apply_settings(config.world, 0);
+        Gym {
+            sim,
+            env: Environment::headless(config.world),
+            next_seed: config.seed,
+            steps: 0,
+            caught: vec![false; config.agents.len()],
+            rewards: vec![
+                Box::new(Catches(1.0)),
+                Box::new(Survival {
+                    per_step: 0.01,
+                    caught: -1.0,
+                }),
+            ],
+            config,
+        }
+    }
+
+    // Length of each agent's observation.
+    pub fn observation_size(&self) -> usize {
+        OWN_VALUES + 2 * self.config.neighbours * PER_BOID
+    }
+
+    // Starts a new episode from `seed`, or from the next seed along, and
+    // returns each agent's first observation.
+    pub fn reset(&mut self, seed: Option<u64>) -> Vec<Vec<f32>> {
+        let seed = seed.unwrap_or(self.next_seed);
+        self.next_seed = seed.wrapping_add(1);
+        self.sim.restart(seed, self.config.world);
+        self.sim.apply_settings(self.config.world, 0);
+        for i in 0..self.config.agents.len() {
+            *self.action_mut(i) = Some(Vec2::ZERO);
+        }
+        self.steps = 0;
+        self.caught.fill(false);
+        self.observations()
+    }
+
+    // Steers each agent by its action, a direction whose length is capped at 1
+    // and scaled by the boid's max force, then advances the simulation.
+    pub fn step(&mut self, actions: &[Vec2]) -> Result<Step, String> {
+        if actions.len() != self.config.agents.len() {
+            return Err(format!(
+                "expected {} actions, one per agent, got {}",
+                self.config.agents.len(),
+                actions.len()
+            ));
+        }
+        for (i, action) in actions.iter().enumerate() {
+            if !action.is_finite() {
+                return Err(format!("action {i} isn't a finite number"));
+            }
+            let max_force = self.agent(i).max_force;
+            *self.action_mut(i) = Some(action.clamp_length_max(1.0) * max_force);
+        }
+        self.sim.step(&self.env);
+        self.steps += 1;
+
+        let mut rewards = Vec::with_capacity(actions.len());
+        for (i, agent) in self.config.agents.iter().enumerate() {
+            let outcome = Outcome {
+                catches: match agent.boid_type {
+                    Predator => self
+                        .sim
+                        .catches
+                        .iter()
+                        .filter(|c| c.predator == agent.index)
+                        .count(),
+                    Prey => 0,
+                },
+                caught: agent.boid_type == Prey
+                    && self.sim.catches.iter().any(|c| c.prey == agent.index),
+            };
+            self.caught[i] |= outcome.caught;
+            rewards.push(
+                self.rewards
+                    .iter()
+                    .map(|hook| hook.reward(agent, &outcome, &self.sim))
+                    .sum(),
+            );
+        }
+
+        let prey_caught: Vec<bool> = self
+            .config
+            .agents
+            .iter()
+            .zip(&self.caught)
+            .filter(|(agent, _)| agent.boid_type == Prey)
+            .map(|(_, caught)| *caught)
+            .collect();
+        Ok(Step {
+            observations: self.observations(),
+            rewards,
+            terminated: !prey_caught.is_empty() && prey_caught.iter().all(|c| *c),
+            truncated: self.steps >= self.config.max_steps,
+        })
+    }
+
+    pub fn observations(&self) -> Vec<Vec<f32>> {
+        (0..self.config.agents.len())
+            .map(|i| self.observe(i))
+            .collect()
+    }
+
+    // What agent `i` sees, all relative to itself: its velocity, how far each
+    // wall is, then the nearest prey and the nearest predators, closest first.
+    // Distances are in visual ranges, velocities in max speeds, and wall
+    // distances in world widths or heights.
+    pub fn observe(&self, i: usize) -> Vec<f32> {
+        let agent = self.config.agents[i];
+        let boid = self.agent(i);
+        let bounds = self.config.world;
+        let (left, right, bottom, top) = bounds.l_r_b_t();
+        let mut observation = Vec::with_capacity(self.observation_size());
+        let velocity = boid.velocity / boid.max_speed;
+        observation.extend([velocity.x, velocity.y]);
+        observation.extend([
+            (boid.position.x - left) / bounds.w(),
+            (right - boid.position.x) / bounds.w(),
+            (boid.position.y - bottom) / bounds.h(),
+            (top - boid.position.y) / bounds.h(),
+        ]);
+        for (boid_type, others) in [(Prey, &self.sim.flock), (Predator, &self.sim.predators)] {
+            let skip = (boid_type == agent.boid_type).then_some(agent.index);
+            let nearest = nearest(boid, others, skip, self.config.neighbours);
+            for slot in 0..self.config.neighbours {
+                match nearest.get(slot) {
+                    Some(other) => {
+                        let offset = (other.position - boid.position) / boid.visual_range;
+                        let velocity = (other.velocity - boid.velocity) / boid.max_speed;
+                        observation.extend([offset.x, offset.y, velocity.x, velocity.y, 1.0]);
+                    }
+                    None => observation.extend([0.0; PER_BOID]),
+                }
+            }
+        }
+        observation
+    }
+
+    fn agent(&self, i: usize) -> &Boid {
+        let agent = self.config.agents[i];
+        match agent.boid_type {
+            Prey => &self.sim.flock[agent.index],
+            Predator => &self.sim.predators[agent.index],
+        }
+    }
+
+    fn action_mut(&mut self, i: usize) -> &mut Option<Vec2> {
+        let agent = self.config.agents[i];
+        match agent.boid_type {
+            Prey => &mut self.sim.flock[agent.index].action,
+            Predator => &mut self.sim.predators[agent.index].action,
+        }
+    }
+}
+
+// Up to `k` of `others` closest to `boid`, nearest first, leaving out `skip`.
+fn nearest<'a>(boid: &Boid, others: &'a [Boid], skip: Option<usize>, k: usize) -> Vec<&'a Boid> {
+    let mut candidates: Vec<(f32, &Boid)> = others
+        .iter()
+        .enumerate()
+        .filter(|(j, _)| Some(*j) != skip)
+        .map(|(_, other)| (boid.position.distance_squared(other.position), other))
+        .collect();
+    if candidates.len() > k {
+        candidates.select_nth_unstable_by(k, |a, b| a.0.total_cmp(&b.0));
+        candidates.truncate(k);
+    }
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+    candidates.into_iter().map(|(_, other)| other).collect()
+}
+
+// Runs a few episodes with a hand-written policy, chasing the nearest prey or
+// fleeing the nearest predator, as a baseline for learned ones to beat.
+// Started with `--gym predator` or `--gym prey`.
+pub fn run(species: &str) {
+    let boid_type = match species.to_ascii_lowercase().as_str() {
+        "predator" | "predators" => Predator,
+        "prey" => Prey,
+        _ => {
+            eprintln!("Can't start the environment: expected predator or prey, not {species:?}");
+            std::process::exit(1);
+        }
+    };
+    let number = |name: &str, default: usize| {
+        arg(name).map_or(default, |value| {
+            value.parse().unwrap_or_else(|_| {
+                eprintln!("Ignoring {name} {value:?}, expected a whole number");
+                default
+            })
+        })
+    };
+    let world = world_from_args()
+        .unwrap_or_else(|| Rect::from_w_h(DEFAULT_WORLD_SIZE.0, DEFAULT_WORLD_SIZE.1));
+    let registry = registry();
+    let mut sim = Simulation::new(
+        0,
+        world,
+        BoidOptions::prey(&registry),
+        BoidOptions::predators(&registry),
+    );
+    if let Some(preset) = preset_from_args() {
+        preset.blend_into(&mut sim, 1.0);
+        sim.apply_settings(world, 0);
+    }
+    let config = Config {
+        world,
+        seed: seed_from_args().unwrap_or(0),
+        agents: (0..number("--agents", 1).max(1))
+            .map(|index| Agent { boid_type, index })
+            .collect(),
+        neighbours: number("--neighbours", 5),
+        max_steps: number("--steps", 1000),
+    };
+    let episodes = number("--episodes", 5);
+    let mut gym = Gym::new(sim, config);
+    println!(
+        "{} agents, observations of {} values",
+        gym.config.agents.len(),
+        gym.observation_size()
+    );
+
+    // The first prey and predator slots come straight after the agent's own values.
+    let neighbours = gym.config.neighbours;
+    let slot = match boid_type {
+        Prey => OWN_VALUES + neighbours * PER_BOID,
+        Predator => OWN_VALUES,
+    };
+    for episode in 0..episodes {
+        let mut observations = gym.reset(None);
+        let mut returns = vec![0.0; gym.config.agents.len()];
+        let mut steps = 0;
+        loop {
+            let actions: Vec<Vec2> = observations
+                .iter()
+                .map(|o| {
+                    let towards = vec2(o[slot], o[slot + 1]).normalize_or_zero();
+                    match (neighbours > 0 && o[slot + 4] > 0.0, boid_type) {
+                        (true, Predator) => towards,
+                        (true, Prey) => -towards,
+                        (false, _) => vec2(o[0], o[1]).normalize_or_zero(),
+                    }
+                })
+                .collect();
+            let step = gym.step(&actions).expect("one action per agent");
+            for (total, reward) in returns.iter_mut().zip(&step.rewards) {
+                *total += reward;
+            }
+            observations = step.observations;
+            steps += 1;
+            if step.terminated || step.truncated {
+                break;
+            }
+        }
+        let returns: Vec<String> = returns.iter().map(|r| format!("{r:.2}")).collect();
+        println!(
+            "Episode {episode}: {steps} steps, returns {}",
+            returns.join(", ")
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+
+use nannou::prelude::*;
+
+use crate::{
+    boids::Boid,
+    leader::Leader,
+    model::{BoidOptions, Model},
+    simulation::Simulation,
+    theme::faded,
+};
+
+// Steps are taken once a frame at normal speed, so this many make a second.
+const STEPS_PER_SECOND: usize = 60;
+
+// Just enough of a boid to put it back where it was. Everything else is
+// either a setting, which is left as it is now so a rewind can be replayed
+// differently, or worked out again on the next step.
+#[derive(Clone, Copy)]
+struct BoidState {
+    position: Vec2,
+    velocity: Vec2,
+    age: u32,
+    leader: Option<Leader>,
+}
+
+impl BoidState {
+    fn of(boid: &Boid) -> BoidState {
+        BoidState {
+            position: boid.position,
+            velocity: boid.velocity,
+            age: boid.age,
+            leader: boid.leader,
+        }
+    }
+
+    fn restore(self, boid: &mut Boid) {
+        boid.position = self.position;
+        boid.velocity = self.velocity;
+        boid.acceleration = Vec2::ZERO;
+        boid.age = self.age;
+        boid.leader = self.leader;
+        boid.trail.clear();
+    }
+}
+
+// One simulation's boids at one step.
+struct Side {
+    flock: Vec<BoidState>,
+    predators: Vec<BoidState>,
+}
+
+// Every simulation at one step, in the same order as `Model::sims`.
+struct Frame {
+    sides: Vec<Side>,
+}
+
+// The last few seconds of the simulation, kept so it can be scrubbed back
+// through and resumed from any point.
+pub struct History {
+    pub seconds: usize,
+    frames: VecDeque<Frame>,
+    // The frame on screen while scrubbing, or `None` when live.
+    position: Option<usize>,
+    dragging: bool,
+}
+
+impl History {
+    pub fn new(seconds: usize) -> History {
+        History {
+            seconds,
+            frames: VecDeque::new(),
+            position: None,
+            dragging: false,
+        }
+    }
+
+    pub fn record(&mut self, sims: &[Simulation]) {
+        let sides = sims
+            .iter()
+            .map(|sim| Side {
+                flock: sim.flock.iter().map(BoidState::of).collect(),
+                predators: sim.predators.iter().map(BoidState::of).collect(),
+            })
+            .collect();
+        self.frames.push_back(Frame { sides });
+        while self.frames.len() > self.seconds * STEPS_PER_SECOND {
+            self.frames.pop_front();
+        }
+    }
+
+    // Forgets everything after the frame being shown, so the simulation can
+    // carry on from there.
+    pub fn resume(&mut self) {
+        if let Some(position) = self.position.take() {
+            self.frames.truncate(position + 1);
+        }
+    }
+
+    // Drops every frame, for when the simulations they were recorded from are gone.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.position = None;
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.position.or(self.frames.len().checked_sub(1))
+    }
+
+    // How far back the frame on screen is, in seconds at normal speed.
+    #[allow(clippy::cast_precision_loss)]
+    fn seconds_back(&self) -> f32 {
+        let latest = self.frames.len().saturating_sub(1);
+        (latest - self.current().unwrap_or(latest)) as f32 / STEPS_PER_SECOND as f32
+    }
+}
+
+// Shows frame `index`, pausing the simulation there.
+pub fn scrub_to(model: &mut Model, index: usize) {
+    let Some(frame) = model.history.frames.get(index) else {
+        return;
+    };
+    for (sim, side) in model.sims.iter_mut().zip(&frame.sides) {
+        restore(&side.flock, &mut sim.flock, &mut sim.boid_options);
+        restore(
+            &side.predators,
+            &mut sim.predators,
+            &mut sim.predator_options,
+        );
+    }
+    model.history.position = Some(index);
+    model.clock.paused = true;
+}
+
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+pub fn scrub_by(model: &mut Model, steps: isize) {
+    let Some(current) = model.history.current() else {
+        return;
+    };
+    let last = model.history.frames.len() - 1;
+    let index = (current as isize + steps).clamp(0, last as isize) as usize;
+    scrub_to(model, index);
+}
+
+fn restore(states: &[BoidState], boids: &mut Vec<Boid>, options: &mut BoidOptions) {
+    boids.truncate(states.len());
+    while boids.len() < states.len() {
+        boids.push(Boid::new(0.0, 0.0, options.boid_type));
+    }
+    for (boid, state) in boids.iter_mut().zip(states) {
+        options.apply(boid);
+        state.restore(boid);
+    }
+    options.flock_size = boids.len();
+    options.n_mod = 0;
+}
+
+// The timeline is only there while paused, and out of the way otherwise.
+pub fn timeline_visible(model: &Model) -> bool {
+    model.show_text && model.clock.paused && model.history.frames.len() > 1
+}
+
+fn timeline_rect(app: &App) -> Rect {
+    let window = app.window_rect();
+    Rect::from_x_y_w_h(0.0, window.bottom() + 20.0, window.w() * 0.4, 10.0)
+}
+
+// Called on a mouse press, returning whether it landed on the timeline.
+pub fn start_drag(app: &App, model: &mut Model) -> bool {
+    model.history.dragging =
+        timeline_visible(model) && timeline_rect(app).contains(app.mouse.position());
+    model.history.dragging
+}
+
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn update_timeline(app: &App, model: &mut Model) {
+    if !model.history.dragging || !app.mouse.buttons.left().is_down() {
+        model.history.dragging = false;
+        return;
+    }
+    let bar = timeline_rect(app);
+    let fraction = ((app.mouse.x - bar.left()) / bar.w()).clamp(0.0, 1.0);
+    let last = model.history.frames.len().saturating_sub(1);
+    scrub_to(model, (fraction * last as f32).round() as usize);
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn draw_timeline(app: &App, model: &Model, draw: &Draw) {
+    if !timeline_visible(model) {
+        return;
+    }
+    let bar = timeline_rect(app);
+    let theme = model.theme();
+    let last = model.history.frames.len() - 1;
+    let fraction = model.history.current().unwrap_or(last) as f32 / last as f32;
+    draw.rect()
+        .xy(bar.xy())
+        .wh(bar.wh())
+        .color(faded(theme.text, 0.15));
+    let played = bar.pad_right(bar.w() * (1.0 - fraction));
+    draw.rect()
+        .xy(played.xy())
+        .wh(played.wh())
+        .color(faded(theme.overlay, 0.6));
+    let label = match model.history.seconds_back() {
+        back if back > 0.0 => format!("-{back:.1}s"),
+        _ => "now".to_string(),
+    };
+    draw.text(&label)
+        .xy(bar.mid_top() + vec2(0.0, 12.0))
+        .font_size(11)
+        .color(theme.text);
+}
@@ -1,74 +1,217 @@
-use std::process::exit;
+use nannou::{
+    prelude::{vec2, Key, MouseButton, MouseScrollDelta, TouchPhase, Vec2},
+    App,
+};
 
-use nannou::{prelude::Key, App};
+use crate::{
+    bindings::Action,
+    history::{scrub_by, start_drag},
+    model::{Model, MODIFIER_RANGE},
+    path::PathMode,
+    preset::Preset,
+    sonify::toggle_recording,
+};
 
-use crate::model::Model;
+// How far, in screen pixels, each press of a pan key moves the view.
+const PAN_STEP: f32 = 50.0;
 
 pub fn key_pressed(app: &App, model: &mut Model, key: Key) {
-    match key {
-        Key::S => {
+    if model.gui.is_editing() {
+        return;
+    }
+    if let Some(action) = model.bindings.action(key, app.keys.mods) {
+        perform(app, model, action);
+    }
+}
+
+pub fn perform(app: &App, model: &mut Model, action: Action) {
+    match action {
+        Action::Screenshot => {
             if let Some(window) = app.window(model.main_window) {
                 window.capture_frame(app.exe_name().expect("Problem saving image") + ".png");
             }
         }
-        Key::U => {
-            model.alignment_modifier -= 0.05;
+        Action::ToggleRecording => toggle_recording(model),
+        Action::AlignmentDown => {
+            nudge(model, "Alignment", -0.05);
         }
-        Key::C => {
+        Action::CycleCursorMode => {
             model.cursor_mode = model.cursor_mode.next();
         }
-        Key::T => {
-            model.theme = model.theme.next();
+        Action::CycleTheme => {
+            model.theme_index = (model.theme_index + 1) % model.themes.len();
+        }
+        Action::CycleNeighbourhood => {
+            let options = &mut model.sim_mut().boid_options;
+            options.neighbourhood = options.neighbourhood.next();
+        }
+        Action::CyclePredatorNeighbourhood => {
+            let options = &mut model.sim_mut().predator_options;
+            options.neighbourhood = options.neighbourhood.next();
         }
 
-        Key::I => {
-            model.alignment_modifier += 0.05;
+        Action::AlignmentUp => {
+            nudge(model, "Alignment", 0.05);
         }
-        Key::J => {
-            model.cohesion_modifier -= 0.05;
+        Action::CohesionDown => {
+            nudge(model, "Cohesion", -0.05);
         }
-        Key::K => {
-            model.cohesion_modifier += 0.05;
+        Action::CohesionUp => {
+            nudge(model, "Cohesion", 0.05);
         }
-        Key::M => {
-            model.separation_modifier -= 0.05;
+        Action::SeparationDown => {
+            nudge(model, "Separation", -0.05);
         }
-        Key::Comma => {
-            model.separation_modifier += 0.05;
+        Action::SeparationUp => {
+            nudge(model, "Separation", 0.05);
         }
-        Key::Up => {
-            if model.boid_options.flock_size >= 1000 {
+        Action::AddBoids => {
+            let options = &mut model.sim_mut().boid_options;
+            if options.flock_size >= 1000 {
                 return;
             }
-            if model.boid_options.flock_size < 5 {
-                model.boid_options.n_mod += 1;
+            if options.flock_size < 5 {
+                options.n_mod += 1;
             } else {
-                model.boid_options.n_mod += 5;
+                options.n_mod += 5;
             };
         }
-        Key::Down => {
-            if model.boid_options.flock_size == 0 {
+        Action::RemoveBoids => {
+            let options = &mut model.sim_mut().boid_options;
+            if options.flock_size == 0 {
                 return;
             }
-            if model.boid_options.flock_size > 5 {
-                model.boid_options.n_mod -= 5;
-            } else if model.boid_options.flock_size > 1 {
-                model.boid_options.n_mod -= 1;
+            if options.flock_size > 5 {
+                options.n_mod -= 5;
+            } else if options.flock_size > 1 {
+                options.n_mod -= 1;
             };
         }
-        Key::Left => model.predator_options.n_mod -= 1,
-        Key::Right => {
-            if model.predator_options.flock_size > 5 {
+        Action::RemovePredator => model.sim_mut().predator_options.n_mod -= 1,
+        Action::AddPredator => {
+            let options = &mut model.sim_mut().predator_options;
+            if options.flock_size > 5 {
                 return;
             }
-            model.predator_options.n_mod += 1;
+            options.n_mod += 1;
+        }
+        Action::RemoveLeader => {
+            model.leader_count = model.leader_count.saturating_sub(1);
+        }
+        Action::AddLeader => {
+            model.leader_count += 1;
+        }
+        Action::CycleLeaderTarget => {
+            model.leader_target = model.leader_target.next();
         }
-        Key::H => {
+        Action::ToggleFollowQueue => {
+            model.follow_queue = !model.follow_queue;
+        }
+        Action::CyclePathMode => {
+            model.path_mode = model.path_mode.next();
+        }
+        Action::ClearPath => {
+            model.path.clear();
+        }
+        Action::CycleDebugOverlay => {
+            model.debug_overlay = model.debug_overlay.next();
+        }
+        Action::ToggleTrails => {
+            model.show_trails = !model.show_trails;
+        }
+        Action::CycleColourMode => {
+            model.colour_mode = model.colour_mode.next();
+        }
+        Action::CycleGradient => {
+            let gradient = model.gradient(model.colour_mode).next();
+            model.gradients.insert(model.colour_mode, gradient);
+        }
+        Action::ToggleText => {
             model.show_text = !model.show_text;
         }
-        Key::Q => {
-            exit(1);
+        Action::LoadPreset(i) => {
+            model.preset_target = model.presets.get(i).cloned();
+        }
+        Action::ExportPreset => {
+            let code = Preset::capture(model).encode();
+            println!("Preset code: {code}");
+            model.preset_code = Some(code);
+        }
+        Action::PanLeft => model.camera.pan(vec2(-PAN_STEP, 0.0)),
+        Action::PanRight => model.camera.pan(vec2(PAN_STEP, 0.0)),
+        Action::PanUp => model.camera.pan(vec2(0.0, PAN_STEP)),
+        Action::PanDown => model.camera.pan(vec2(0.0, -PAN_STEP)),
+        Action::ResetView => model.camera.reset(model.world, app.window_rect()),
+        Action::CycleResizeMode => model.resize_mode = model.resize_mode.next(),
+        Action::TogglePause => model.clock.paused = !model.clock.paused,
+        Action::StepOnce => model.clock.step_once(),
+        Action::SlowDown => model.clock.slower(),
+        Action::SpeedUp => model.clock.faster(),
+        Action::ScrubBack => scrub_by(model, -1),
+        Action::ScrubForward => scrub_by(model, 1),
+        Action::ToggleComparison => model.toggle_comparison(),
+        Action::SwitchSide => model.active = (model.active + 1) % model.sims.len(),
+        Action::Restart => model.restart(),
+        Action::Quit => {
+            app.quit();
         }
-        _other_key => {}
     }
 }
+
+// Changes the weight of one of the active simulation's prey behaviours.
+fn nudge(model: &mut Model, behaviour: &str, by: f32) {
+    if let Some(weight) = model.sim_mut().boid_options.steering.weight_mut(behaviour) {
+        *weight = (*weight + by).clamp(*MODIFIER_RANGE.start(), *MODIFIER_RANGE.end());
+    }
+}
+
+pub fn mouse_pressed(app: &App, model: &mut Model, button: MouseButton) {
+    if model.gui.contains(app.mouse.position()) {
+        return;
+    }
+    if button == MouseButton::Left && start_drag(app, model) {
+        return;
+    }
+    if button == MouseButton::Left {
+        // Clicking either half of a comparison makes it the one being changed.
+        let (side, mouse) = model.mouse_in_view(app);
+        model.active = side;
+        model.selected = model.sim().boid_at(model.cursor(app));
+        model.camera.start_drag(mouse);
+    }
+    if button == MouseButton::Right {
+        // Only the built-in loop is closed, and a drawn path starts afresh
+        // rather than carrying on from it.
+        if model.path.closed {
+            model.path.clear();
+        }
+        model.path.points.push(model.cursor(app));
+        if model.path_mode == PathMode::Off {
+            model.path_mode = PathMode::Polyline;
+        }
+    }
+}
+
+// Scrolls the control panel when over it, and zooms the camera everywhere else.
+pub fn mouse_wheel(app: &App, model: &mut Model, delta: MouseScrollDelta, _phase: TouchPhase) {
+    let lines = match delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        #[allow(clippy::cast_possible_truncation)]
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+    };
+    if model.gui.contains(app.mouse.position()) {
+        model.gui.scroll(lines);
+    } else {
+        let (_, mouse) = model.mouse_in_view(app);
+        model.camera.zoom_at(mouse, 1.1_f32.powf(lines));
+    }
+}
+
+pub fn resized(_app: &App, model: &mut Model, size: Vec2) {
+    let world = model.camera.resize(size, model.world, model.resize_mode);
+    model.set_world(world);
+}
+
+pub fn received_character(_app: &App, model: &mut Model, character: char) {
+    model.gui.type_character(character);
+}
@@ -0,0 +1,47 @@
+use nannou::{prelude::*, rand::Rng};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LeaderTarget {
+    Wander,
+    Waypoints,
+    Cursor,
+}
+
+impl LeaderTarget {
+    pub fn next(&self) -> LeaderTarget {
+        match *self {
+            LeaderTarget::Wander => LeaderTarget::Waypoints,
+            LeaderTarget::Waypoints => LeaderTarget::Cursor,
+            LeaderTarget::Cursor => LeaderTarget::Wander,
+        }
+    }
+}
+
+// Per-leader steering state, kept on the boid so leaders don't all share a route.
+#[derive(PartialEq, Clone, Copy)]
+pub struct Leader {
+    pub wander_angle: f32,
+    pub waypoint: usize,
+}
+
+impl Leader {
+    pub fn new(waypoint: usize, rng: &mut impl Rng) -> Leader {
+        Leader {
+            wander_angle: rng.gen_range(-PI..PI),
+            waypoint,
+        }
+    }
+}
+
+// A loop around the middle of the window, far enough in that leaders
+// aren't fighting `avoid_bounds` to reach it.
+pub fn waypoints(bounds: &Rect) -> [Vec2; 4] {
+    let (w, h) = (bounds.w() * 0.3, bounds.h() * 0.3);
+    let centre = bounds.xy();
+    [
+        centre + vec2(-w, h),
+        centre + vec2(w, h),
+        centre + vec2(w, -h),
+        centre + vec2(-w, -h),
+    ]
+}
@@ -0,0 +1,92 @@
+// The simulator as a library, shared by the app, its headless tools and the
+// Python bindings.
+use model::Model;
+use nannou::prelude::*;
+pub mod bindings;
+pub mod boids;
+pub mod camera;
+pub mod clock;
+pub mod colour;
+pub mod cursor;
+pub mod debug;
+pub mod flock;
+pub mod gui;
+pub mod gym;
+pub mod history;
+pub mod keymaps;
+pub mod leader;
+pub mod model;
+pub mod neighbourhood;
+pub mod optimise;
+pub mod osc;
+pub mod path;
+pub mod preset;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod session;
+pub mod simulation;
+pub mod sonify;
+pub mod spatial;
+pub mod steering;
+pub mod theme;
+pub mod ui;
+pub mod update;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+use colour::draw_colour_legend;
+use debug::{draw_debug_legend, draw_debug_overlay};
+use history::draw_timeline;
+use session::draw_remote_cursors;
+use ui::{draw_active_side, draw_information_text, draw_inspector, draw_path, draw_world_bounds};
+
+// needless_pass_by_value added as Nannou framework expects Frame, not &Frame
+#[allow(clippy::needless_pass_by_value)]
+pub fn view(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+    app.window(model.main_window)
+        .expect("Error retrieving main window")
+        .set_cursor_visible(false);
+
+    draw.background().color(model.theme().background);
+    draw_information_text(app, model, &draw);
+    draw_colour_legend(app, model, &draw);
+
+    // Everything in a simulation is drawn through the camera, clipped to its
+    // half of the window when comparing, while text and the control panel stay
+    // put on screen.
+    let viewports = model.viewports(app.window_rect());
+    for (i, (sim, viewport)) in model.sims.iter().zip(viewports).enumerate() {
+        let world = model
+            .camera
+            .transform(&draw.scissor(viewport).xy(viewport.xy()));
+        draw_world_bounds(model, &world);
+        draw_path(model, &world);
+        for boid in sim.flock.iter().chain(&sim.predators) {
+            boid.show_trail(&world, model);
+        }
+        for predator in &sim.predators {
+            predator.show(&world, model);
+        }
+        for boid in &sim.flock {
+            boid.show(&world, model);
+        }
+        draw_debug_overlay(model, sim, &world);
+        if i == model.active {
+            draw_remote_cursors(model, &world);
+            draw_inspector(app, model, &draw, &world);
+        }
+    }
+    draw_debug_legend(app, model, &draw);
+    draw_active_side(app, model, &draw);
+    draw_timeline(app, model, &draw);
+    model.gui.draw(&draw, model.theme());
+    draw.ellipse()
+        .xy(app.mouse.position())
+        .stroke_color(model.theme().cursor)
+        .color(model.theme().cursor)
+        .height(5.0)
+        .width(5.0);
+
+    draw.to_frame(app, &frame)
+        .expect("WHOA NELLY THIS AINT GOOD");
+}
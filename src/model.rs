@@ -1,61 +1,276 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
 use crate::{
-    boids::{
-        Boid,
-        BoidType::{self, Predator, Prey},
-    },
+    bindings::Bindings,
+    boids::{Boid, BoidType},
+    camera::{Camera, ResizeMode},
+    clock::Clock,
+    colour::{ColourMode, Gradient},
     cursor::CursorMode,
-    keymaps::key_pressed,
+    debug::DebugOverlay,
+    gui::Gui,
+    history::History,
+    keymaps::{key_pressed, mouse_pressed, mouse_wheel, received_character, resized},
+    leader::LeaderTarget,
+    neighbourhood::Neighbourhood,
+    osc::OscSender,
+    path::{Path, PathMode},
+    preset::Preset,
+    session::Session,
+    simulation::{Environment, Simulation},
+    sonify::Recording,
+    steering::{Registry, Steering},
     theme::Theme,
-    view, Flock,
+    view,
 };
 
-use nannou::prelude::*;
+use nannou::{prelude::*, rand::Rng};
+
+// Alignment, cohesion and separation modifiers stay within this range, whether
+// set from the keyboard or the control panel.
+pub const MODIFIER_RANGE: RangeInclusive<f32> = 0.0..=2.0;
+// Limits on the rest of the settings, shared by the control panel and anything
+// else that sets them, like preset codes.
+pub const MAX_FORCE_RANGE: RangeInclusive<f32> = 0.05..=1.0;
+pub const SPEED_RANGE: RangeInclusive<f32> = 0.5..=8.0;
+pub const START_SPEED_RANGE: RangeInclusive<f32> = 0.1..=5.0;
+pub const VISION_RANGE: RangeInclusive<f32> = 10.0..=300.0;
+pub const PROTECTION_RANGE: RangeInclusive<f32> = 5.0..=100.0;
+pub const NEAREST_K_RANGE: RangeInclusive<usize> = 1..=30;
+pub const MAX_PREY: usize = 1000;
+pub const MAX_PREDATORS: usize = 6;
+pub const WORLD_SIZE_RANGE: RangeInclusive<f32> = 200.0..=10000.0;
+// Rates, in sends a second, that OSC and WebSocket streams can be asked for.
+// Outside this, the time between sends is too long or too short to keep.
+pub const RATE_RANGE: RangeInclusive<f32> = 0.01..=1000.0;
 
+// Distances, speeds and sizes are all in world units. The default world is the
+// size of nannou's default window, so there one unit is one point.
+pub const DEFAULT_WORLD_SIZE: (f32, f32) = (1024.0, 768.0);
+
+#[derive(Clone)]
 pub struct BoidOptions {
     pub boid_type: BoidType,
     pub starting_velocity: f32,
-    pub colour: Rgb8,
     pub min_speed: f32,
     pub max_speed: f32,
+    pub visual_range: f32,
+    pub protected_range: f32,
     pub flock_size: usize,
     pub n_mod: i32,
+    pub neighbourhood: Neighbourhood,
+    pub steering: Steering,
+}
+
+impl BoidOptions {
+    pub fn prey(registry: &Registry) -> BoidOptions {
+        BoidOptions::new(BoidType::Prey, 1.0, 100, registry)
+    }
+
+    pub fn predators(registry: &Registry) -> BoidOptions {
+        BoidOptions::new(BoidType::Predator, 1.5, 1, registry)
+    }
+
+    // Speeds and ranges start out as a plain `Boid::new` has them, which is
+    // what every boid flew with before these options were applied to it.
+    fn new(
+        boid_type: BoidType,
+        starting_velocity: f32,
+        flock_size: usize,
+        registry: &Registry,
+    ) -> BoidOptions {
+        let boid = Boid::new(0.0, 0.0, boid_type);
+        BoidOptions {
+            boid_type,
+            starting_velocity,
+            min_speed: boid.min_speed,
+            max_speed: boid.max_speed,
+            visual_range: boid.visual_range,
+            protected_range: boid.protected_range,
+            flock_size,
+            n_mod: 0,
+            neighbourhood: Neighbourhood::Metric,
+            steering: registry.steering_for(boid_type),
+        }
+    }
+
+    // A new boid somewhere in `bounds`, with each part of its velocity
+    // anywhere up to `starting_velocity` either way.
+    pub fn spawn(&self, bounds: Rect, rng: &mut impl Rng) -> Boid {
+        let (left, right, bottom, top) = bounds.l_r_b_t();
+        let x = rng.gen_range(left..=right);
+        let y = rng.gen_range(bottom..=top);
+        let mut boid = Boid::new(x, y, self.boid_type);
+        boid.velocity =
+            vec2(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0)) * self.starting_velocity;
+        self.apply(&mut boid);
+        boid
+    }
+
+    // Pushes the species parameters onto a boid, so changes take effect immediately.
+    pub fn apply(&self, boid: &mut Boid) {
+        boid.min_speed = self.min_speed.min(self.max_speed);
+        boid.max_speed = self.max_speed;
+        boid.visual_range = self.visual_range;
+        boid.protected_range = self.protected_range;
+    }
 }
 
 pub struct Model {
     pub main_window: WindowId,
-    pub boid_options: BoidOptions,
-    pub predator_options: BoidOptions,
     pub cursor_mode: CursorMode,
     pub show_text: bool,
-    pub theme: Theme,
-    pub flock: Vec<Boid>,
-    pub predators: Vec<Boid>,
-    pub alignment_modifier: f32,
-    pub cohesion_modifier: f32,
-    pub separation_modifier: f32,
-    pub max_force: f32,
+    pub themes: Vec<Theme>,
+    pub theme_index: usize,
+    // One simulation normally, or two when comparing settings side by side.
+    pub sims: Vec<Simulation>,
+    // The simulation that keys and the control panel change.
+    pub active: usize,
+    // Where every simulation's randomness starts from, so runs can be repeated.
+    pub seed: u64,
+    pub leader_count: usize,
+    pub leader_target: LeaderTarget,
+    pub leader_weight: f32,
+    pub follow_queue: bool,
+    pub path: Path,
+    pub path_mode: PathMode,
+    pub path_weight: f32,
+    pub selected: Option<usize>,
+    pub debug_overlay: DebugOverlay,
+    pub show_trails: bool,
+    pub trail_length: usize,
+    pub trail_opacity: f32,
+    pub colour_mode: ColourMode,
+    pub gradients: HashMap<ColourMode, Gradient>,
+    pub gui: Gui,
+    // Every steering behaviour species can be given, built in or added.
+    pub registry: Registry,
+    pub bindings: Bindings,
+    pub presets: Vec<Preset>,
+    // The preset being eased towards, if a switch is still in progress.
+    pub preset_target: Option<Preset>,
+    // The last code shared with the export action, shown until the next one.
+    pub preset_code: Option<String>,
+    // The area boids live in, which can be bigger or smaller than the window.
+    pub world: Rect,
+    pub camera: Camera,
+    pub resize_mode: ResizeMode,
+    pub clock: Clock,
+    pub history: History,
+    // Sharing the flock with others over the network, if hosting or joined.
+    pub session: Option<Session>,
+    // Sending the flock to music and visuals software, if asked to.
+    pub osc: Option<OscSender>,
+    // Capturing frames and a soundtrack, while recording.
+    pub recording: Option<Recording>,
+    // Streaming the flock to dashboards and visualisers, if asked to.
+    #[cfg(feature = "websocket")]
+    pub websocket: Option<crate::websocket::WebSocketServer>,
 }
 
 impl Model {
-    pub fn add_boid(&mut self, bounds: Rect, boid_type: BoidType) {
-        let (left, right, bottom, top) = bounds.l_r_b_t();
-        let boid = Boid::new(
-            random_range(left, right),
-            random_range(bottom, top),
-            boid_type,
-        );
+    pub fn sim(&self) -> &Simulation {
+        &self.sims[self.active]
+    }
+
+    pub fn sim_mut(&mut self) -> &mut Simulation {
+        &mut self.sims[self.active]
+    }
+
+    pub fn comparing(&self) -> bool {
+        self.sims.len() > 1
+    }
+
+    // The part of the window each simulation is drawn in, in the same order as `sims`.
+    pub fn viewports(&self, window: Rect) -> Vec<Rect> {
+        if !self.comparing() {
+            return vec![window];
+        }
+        let half = window.w() / 2.0;
+        vec![window.pad_right(half), window.pad_left(half)]
+    }
+
+    // Which simulation's half the mouse is over, and where it is relative to
+    // the middle of that half. Every half is a view through the same camera.
+    pub fn mouse_in_view(&self, app: &App) -> (usize, Vec2) {
+        let mouse = app.mouse.position();
+        self.viewports(app.window_rect())
+            .iter()
+            .enumerate()
+            .find(|(_, viewport)| viewport.contains(mouse))
+            .map_or((self.active, mouse), |(i, viewport)| {
+                (i, mouse - viewport.xy())
+            })
+    }
+
+    // Changes the world's size, bringing anyone left outside back in.
+    pub fn set_world(&mut self, world: Rect) {
+        self.world = world;
+        for sim in &mut self.sims {
+            for boid in sim.flock.iter_mut().chain(&mut sim.predators) {
+                boid.keep_within(&world);
+            }
+        }
+    }
 
-        match boid_type {
-            Prey => self.flock.push(boid),
-            Predator => self.predators.push(boid),
+    // Where the mouse is in the world, rather than on screen.
+    pub fn cursor(&self, app: &App) -> Vec2 {
+        self.camera.to_world(self.mouse_in_view(app).1)
+    }
+
+    // Splits the screen in two, or goes back to one simulation. Both sides start
+    // over from the seed, so any difference between them comes from their settings.
+    pub fn toggle_comparison(&mut self) {
+        if self.comparing() {
+            let sim = self.sims.swap_remove(self.active);
+            self.sims = vec![sim];
+            self.active = 0;
+        } else {
+            self.sims.push(self.sims[0].clone());
+            // The left keeps the settings it had, and the right is there to be changed.
+            self.active = 1;
+            self.restart();
         }
+        self.selected = None;
+        self.history.clear();
     }
 
-    pub fn remove_boid(&mut self, boid_type: BoidType) {
-        match boid_type {
-            Prey => self.flock.pop(),
-            Predator => self.predators.pop(),
-        };
+    // Respawns every simulation from the seed, keeping their settings.
+    pub fn restart(&mut self) {
+        for sim in &mut self.sims {
+            sim.restart(self.seed, self.world);
+        }
+        self.selected = None;
+        self.history.clear();
+    }
+
+    // The shared inputs to a step, worked out once per frame.
+    pub fn environment(&self, app: &App) -> Environment {
+        Environment {
+            bounds: self.world,
+            cursor: self.cursor(app),
+            cursor_mode: self.cursor_mode,
+            remote_cursors: Vec::new(),
+            leader_target: self.leader_target,
+            leader_weight: self.leader_weight,
+            follow_queue: self.follow_queue,
+            path: self.path.polyline(self.path_mode),
+            path_width: self.path.width,
+            path_weight: self.path_weight,
+            trail_length: if self.show_trails {
+                self.trail_length
+            } else {
+                0
+            },
+        }
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.themes[self.theme_index]
+    }
+
+    pub fn gradient(&self, mode: ColourMode) -> &Gradient {
+        &self.gradients[&mode]
     }
 }
 
@@ -65,51 +280,130 @@ pub fn model(app: &App) -> Model {
         .title(app.exe_name().unwrap_or_default())
         .view(view)
         .key_pressed(key_pressed)
+        .mouse_pressed(mouse_pressed)
+        .mouse_wheel(mouse_wheel)
+        .received_character(received_character)
+        .resized(resized)
         .build()
         .expect("ah geez something happened...i'm sorry.");
 
     let show_text = true;
     // defaults
     let cursor_mode = CursorMode::Ignore;
-    let theme = Theme::Normal;
-    let alignment_modifier = 0.25;
-    let cohesion_modifier = 0.25;
-    let separation_modifier = 0.75;
-    let boid_options = BoidOptions {
-        boid_type: BoidType::Prey,
-        starting_velocity: 1.0,
-        colour: BLACK,
-        min_speed: 1.0,
-        max_speed: 3.0,
-        flock_size: 100,
-        n_mod: 0,
-    };
-    let predator_options = BoidOptions {
-        boid_type: BoidType::Predator,
-        starting_velocity: 1.5,
-        colour: RED,
-        min_speed: 1.0,
-        max_speed: 3.5,
-        flock_size: 1,
-        n_mod: 0,
-    };
-    let max_force = 0.2;
+    let themes = Theme::load_all("themes");
+    let registry = registry();
+    let boid_options = BoidOptions::prey(&registry);
+    let predator_options = BoidOptions::predators(&registry);
+    let leader_count = 0;
+    let leader_target = LeaderTarget::Wander;
+    let leader_weight = 5.0;
+    let follow_queue = false;
+    let world = world_from_args()
+        .unwrap_or_else(|| Rect::from_w_h(DEFAULT_WORLD_SIZE.0, DEFAULT_WORLD_SIZE.1));
+    let camera = Camera::fit(world, app.window_rect());
+    let path = Path::new_loop(world);
+    let path_mode = PathMode::Off;
+    let path_weight = 2.0;
 
-    let flock = Flock::new_flock(app.window_rect(), boid_options.flock_size, Prey);
-    let predators = Flock::new_flock(app.window_rect(), 1, Predator);
+    let seed = seed_from_args().unwrap_or_else(|| nannou::rand::thread_rng().gen());
+    let sim = Simulation::new(seed, world, boid_options, predator_options);
+    let gradients = ColourMode::ALL
+        .into_iter()
+        .map(|mode| (mode, mode.default_gradient()))
+        .collect();
 
-    Model {
+    let mut model = Model {
         main_window,
-        boid_options,
-        predator_options,
         cursor_mode,
         show_text,
-        theme,
-        flock,
-        predators,
-        alignment_modifier,
-        cohesion_modifier,
-        separation_modifier,
-        max_force,
+        themes,
+        theme_index: 0,
+        sims: vec![sim],
+        active: 0,
+        seed,
+        leader_count,
+        leader_target,
+        leader_weight,
+        follow_queue,
+        path,
+        path_mode,
+        path_weight,
+        selected: None,
+        debug_overlay: DebugOverlay::Off,
+        show_trails: false,
+        trail_length: 40,
+        trail_opacity: 0.5,
+        colour_mode: ColourMode::Species,
+        gradients,
+        gui: Gui::new(&["Flocking"]),
+        registry,
+        bindings: Bindings::load_or_default("bindings.toml"),
+        presets: Preset::built_in(),
+        preset_target: None,
+        preset_code: None,
+        world,
+        camera,
+        resize_mode: ResizeMode::Rescale,
+        clock: Clock::new(),
+        history: History::new(10),
+        session: Session::from_args(),
+        osc: OscSender::from_args(),
+        recording: None,
+        #[cfg(feature = "websocket")]
+        websocket: crate::websocket::WebSocketServer::from_args(),
+    };
+    if let Some(preset) = preset_from_args() {
+        preset.blend(&mut model, 1.0);
+    }
+    model
+}
+
+// The built-in steering behaviours, plus any scripts when scripting is enabled.
+pub fn registry() -> Registry {
+    #[allow(unused_mut)]
+    let mut registry = Registry::built_in();
+    #[cfg(feature = "scripting")]
+    crate::script::load_all(&mut registry, "scripts");
+    registry
+}
+
+// The value following `name` on the command line, if it was given.
+pub fn arg(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
+
+// A preset code passed as `--preset <code>`, so shared settings can be opened directly.
+// It can also be the name of a file holding a code, like the optimiser writes.
+pub fn preset_from_args() -> Option<Preset> {
+    let arg = arg("--preset")?;
+    Preset::load(&arg)
+        .map_err(|e| eprintln!("Ignoring preset code {arg:?}: {e}"))
+        .ok()
+}
+
+// A seed passed as `--seed <number>`, to get the same run again.
+pub fn seed_from_args() -> Option<u64> {
+    let seed = arg("--seed")?;
+    seed.parse()
+        .map_err(|_| eprintln!("Ignoring seed {seed:?}, expected a whole number"))
+        .ok()
+}
+
+// World dimensions passed as `--world <width>x<height>`, each within
+// `WORLD_SIZE_RANGE`. That also keeps out infinities and NaN, which would
+// leave nowhere to spawn boids.
+pub fn world_from_args() -> Option<Rect> {
+    let size = arg("--world")?;
+    let parsed = size
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse::<f32>().ok()?, h.parse::<f32>().ok()?)))
+        .filter(|(w, h)| WORLD_SIZE_RANGE.contains(w) && WORLD_SIZE_RANGE.contains(h));
+    if parsed.is_none() {
+        eprintln!(
+            "Ignoring world size {size:?}, expected something like 3000x2000, with each side from {} to {}",
+            WORLD_SIZE_RANGE.start(),
+            WORLD_SIZE_RANGE.end()
+        );
     }
+    parsed.map(|(w, h)| Rect::from_w_h(w, h))
 }
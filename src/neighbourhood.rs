@@ -0,0 +1,24 @@
+// Which flockmates a boid pays attention to for alignment and cohesion.
+// Separation still only acts on the ones inside its protected range.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Neighbourhood {
+    // Everyone within visual range.
+    Metric,
+    // The k nearest, however far away they are.
+    Topological(usize),
+    // The k nearest that are also within visual range.
+    Hybrid(usize),
+}
+
+impl Neighbourhood {
+    // Starlings track roughly seven neighbours.
+    pub const DEFAULT_K: usize = 7;
+
+    pub fn next(&self) -> Neighbourhood {
+        match *self {
+            Neighbourhood::Metric => Neighbourhood::Topological(Self::DEFAULT_K),
+            Neighbourhood::Topological(k) => Neighbourhood::Hybrid(k),
+            Neighbourhood::Hybrid(_) => Neighbourhood::Metric,
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::{ops::RangeInclusive, path::PathBuf, thread};
+
+use nannou::{
+    prelude::*,
+    rand::{rngs::StdRng, Rng, SeedableRng},
+};
+
+use crate::{
+    model::{
+        arg, preset_from_args, registry, seed_from_args, world_from_args, BoidOptions,
+        DEFAULT_WORLD_SIZE, MAX_FORCE_RANGE, MODIFIER_RANGE, PROTECTION_RANGE, SPEED_RANGE,
+        VISION_RANGE,
+    },
+    preset::Preset,
+    simulation::{Environment, Simulation},
+    steering::Registry,
+};
+
+// Every gene, in order, with the range it's kept within. The same limits as the
+// control panel, so the result is something you could have dialled in by hand.
+const GENES: [(&str, RangeInclusive<f32>); 12] = [
+    ("alignment", MODIFIER_RANGE),
+    ("cohesion", MODIFIER_RANGE),
+    ("separation", MODIFIER_RANGE),
+    ("max force", MAX_FORCE_RANGE),
+    ("prey min speed", SPEED_RANGE),
+    ("prey max speed", SPEED_RANGE),
+    ("prey visual range", VISION_RANGE),
+    ("prey protected range", PROTECTION_RANGE),
+    ("predator min speed", SPEED_RANGE),
+    ("predator max speed", SPEED_RANGE),
+    ("predator visual range", VISION_RANGE),
+    ("predator protected range", PROTECTION_RANGE),
+];
+type Genome = [f32; GENES.len()];
+
+// How many of the best genomes carry over unchanged to the next generation.
+const ELITES: usize = 2;
+const TOURNAMENT_SIZE: usize = 3;
+const MUTATION_RATE: f32 = 0.2;
+// How far a mutation can move a gene, as a fraction of its range.
+const MUTATION_SIZE: f32 = 0.1;
+// Metrics that need a pass over the whole flock are only sampled this often.
+const SAMPLE_EVERY: usize = 10;
+
+// What the optimiser is trying to achieve. Higher scores are always better.
+#[derive(Debug, Clone, Copy)]
+pub enum Fitness {
+    // As few catches by predators as possible.
+    Survival,
+    // Order as close to the target as possible.
+    Polarisation(f32),
+    // As few prey bumping into each other as possible.
+    Collisions,
+    // As close to the target number of clusters as possible.
+    Clusters(f32),
+}
+
+impl Fitness {
+    // How many of `GENES` are evolved. Predators are left alone when judging
+    // survival, which could otherwise be won by slowing them down.
+    fn evolved_genes(self) -> usize {
+        match self {
+            Fitness::Survival => 8,
+            _ => GENES.len(),
+        }
+    }
+
+    // Reads e.g. "survival", "polarisation=0.8" or "clusters=3".
+    fn parse(text: &str) -> Result<Fitness, String> {
+        let (name, target) = match text.split_once('=') {
+            Some((name, target)) => {
+                let target = target
+                    .trim()
+                    .parse::<f32>()
+                    .map_err(|_| format!("{target:?} isn't a valid target"))?;
+                (name.trim(), Some(target))
+            }
+            None => (text.trim(), None),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "survival" => Ok(Fitness::Survival),
+            "polarisation" | "polarization" => Ok(Fitness::Polarisation(target.unwrap_or(0.9))),
+            "collisions" => Ok(Fitness::Collisions),
+            "clusters" => Ok(Fitness::Clusters(target.unwrap_or(1.0))),
+            _ => Err(format!(
+                "unknown fitness {name:?}, expected survival, polarisation, collisions or clusters"
+            )),
+        }
+    }
+
+    // The score for one run, as `sim` steps along.
+    #[allow(clippy::cast_precision_loss)]
+    fn score(self, sim: &mut Simulation, env: &Environment, steps: usize) -> f32 {
+        let mut total = 0.0;
+        let mut samples = 0;
+        for step in 0..steps {
+            sim.step(env);
+            let sample = match self {
+                // Catches per thousand steps, so scores don't depend on the run length.
+                Fitness::Survival => -(sim.catches.len() as f32) * 1000.0,
+                // Give the flock the first half of the run to settle before judging it.
+                _ if step < steps / 2 || step % SAMPLE_EVERY != 0 => continue,
+                Fitness::Polarisation(target) => -(sim.metrics().polarisation - target).abs(),
+                Fitness::Collisions => {
+                    sim.rebuild_grid();
+                    -(sim.collisions() as f32)
+                }
+                Fitness::Clusters(target) => {
+                    sim.rebuild_grid();
+                    -(sim.metrics().clusters as f32 - target).abs()
+                }
+            };
+            total += sample;
+            samples += 1;
+        }
+        total / samples.max(1) as f32
+    }
+}
+
+struct Settings {
+    fitness: Fitness,
+    population: usize,
+    generations: usize,
+    steps: usize,
+    // Every genome is judged on the same runs, one per seed.
+    seeds: Vec<u64>,
+    world: Rect,
+    out: PathBuf,
+}
+
+impl Settings {
+    fn from_args(fitness: &str) -> Result<Settings, String> {
+        let number = |name: &str, default: usize| match arg(name) {
+            Some(value) => value
+                .parse::<usize>()
+                .map_err(|_| format!("{name} expects a whole number, not {value:?}")),
+            None => Ok(default),
+        };
+        let seed = seed_from_args().unwrap_or(0);
+        let runs = number("--runs", 3)?.max(1);
+        Ok(Settings {
+            fitness: Fitness::parse(fitness)?,
+            population: number("--population", 24)?.max(ELITES + 1),
+            generations: number("--generations", 20)?,
+            steps: number("--steps", 600)?,
+            seeds: (0..runs as u64)
+                .map(|i| seed.wrapping_add(i * 1000))
+                .collect(),
+            world: world_from_args()
+                .unwrap_or_else(|| Rect::from_w_h(DEFAULT_WORLD_SIZE.0, DEFAULT_WORLD_SIZE.1)),
+            out: arg("--out")
+                .unwrap_or_else(|| "optimised.preset".to_string())
+                .into(),
+        })
+    }
+}
+
+// Evolves flocking settings without opening a window, then writes the best as
+// a preset code. Started with `--optimise <fitness>`.
+pub fn run(fitness: &str) {
+    let settings = match Settings::from_args(fitness) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Can't optimise: {e}");
+            std::process::exit(1);
+        }
+    };
+    let registry = registry();
+    let base = preset_from_args().unwrap_or_else(|| {
+        let sim = Simulation::new(
+            0,
+            settings.world,
+            BoidOptions::prey(&registry),
+            BoidOptions::predators(&registry),
+        );
+        Preset::of(&sim, "Normal")
+    });
+    let mut rng = StdRng::seed_from_u64(settings.seeds[0]);
+    println!(
+        "Optimising for {:?}: {} genomes, {} generations, {} steps x {} runs each",
+        settings.fitness,
+        settings.population,
+        settings.generations,
+        settings.steps,
+        settings.seeds.len()
+    );
+
+    // The starting settings compete too, so the result is never worse than them.
+    let mut population = vec![genes(&base)];
+    while population.len() < settings.population {
+        let mut genome = genes(&base);
+        mutate(&mut genome, settings.fitness, 1.0, 0.5, &mut rng);
+        population.push(genome);
+    }
+
+    let mut best = (f32::NEG_INFINITY, genes(&base));
+    for generation in 0..=settings.generations {
+        let scores = evaluate_all(&population, &base, &settings, &registry);
+        let mut ranked: Vec<(f32, Genome)> = scores.into_iter().zip(population).collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        if ranked[0].0 > best.0 {
+            best = ranked[0];
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let mean = ranked.iter().map(|(score, _)| score).sum::<f32>() / ranked.len() as f32;
+        println!(
+            "Generation {generation}: best {:.4}, mean {mean:.4}",
+            ranked[0].0
+        );
+        if generation == settings.generations {
+            break;
+        }
+        population = next_generation(&ranked, &settings, &mut rng);
+    }
+
+    let preset = Preset {
+        name: "Optimised".to_string(),
+        ..with_genes(&base, &best.1)
+    };
+    let code = preset.encode();
+    for ((name, _), value) in GENES.iter().zip(best.1) {
+        println!("  {name}: {value:.3}");
+    }
+    match std::fs::write(&settings.out, &code) {
+        Ok(()) => println!(
+            "Best score {:.4}, saved to {}. Run it with:\n  boids --preset {}",
+            best.0,
+            settings.out.display(),
+            settings.out.display()
+        ),
+        Err(e) => eprintln!("Couldn't write {}: {e}", settings.out.display()),
+    }
+    println!("Preset code: {code}");
+}
+
+// Scores every genome, spread across as many threads as there are cores.
+fn evaluate_all(
+    population: &[Genome],
+    base: &Preset,
+    settings: &Settings,
+    registry: &Registry,
+) -> Vec<f32> {
+    let threads = thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+    let chunk_size = population.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = population
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|genome| evaluate(&with_genes(base, genome), settings, registry))
+                        .collect::<Vec<f32>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("evaluation thread panicked"))
+            .collect()
+    })
+}
+
+// The mean score over one run per seed.
+#[allow(clippy::cast_precision_loss)]
+fn evaluate(preset: &Preset, settings: &Settings, registry: &Registry) -> f32 {
+    let env = Environment::headless(settings.world);
+    let total: f32 = settings
+        .seeds
+        .iter()
+        .map(|&seed| {
+            let mut sim = Simulation::new(
+                seed,
+                settings.world,
+                BoidOptions::prey(registry),
+                BoidOptions::predators(registry),
+            );
+            preset.blend_into(&mut sim, 1.0);
+            sim.apply_settings(settings.world, 0);
+            // Respawn at the preset's counts, so every genome starts from the same places.
+            sim.restart(seed, settings.world);
+            settings.fitness.score(&mut sim, &env, settings.steps)
+        })
+        .sum();
+    total / settings.seeds.len() as f32
+}
+
+fn next_generation(ranked: &[(f32, Genome)], settings: &Settings, rng: &mut StdRng) -> Vec<Genome> {
+    let mut next: Vec<Genome> = ranked.iter().take(ELITES).map(|(_, g)| *g).collect();
+    while next.len() < settings.population {
+        let (a, b) = (tournament(ranked, rng), tournament(ranked, rng));
+        let mut child = [0.0; GENES.len()];
+        for (i, gene) in child.iter_mut().enumerate() {
+            *gene = if rng.gen_bool(0.5) { a[i] } else { b[i] };
+        }
+        mutate(
+            &mut child,
+            settings.fitness,
+            MUTATION_RATE,
+            MUTATION_SIZE,
+            rng,
+        );
+        next.push(child);
+    }
+    next
+}
+
+// The best of a few genomes picked at random.
+fn tournament(ranked: &[(f32, Genome)], rng: &mut StdRng) -> Genome {
+    (0..TOURNAMENT_SIZE)
+        .map(|_| &ranked[rng.gen_range(0..ranked.len())])
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, genome)| *genome)
+        .expect("tournaments are never empty")
+}
+
+// Nudges each gene with probability `rate`, by up to `size` of its range.
+fn mutate(genome: &mut Genome, fitness: Fitness, rate: f32, size: f32, rng: &mut StdRng) {
+    let evolved = fitness.evolved_genes();
+    for (gene, (_, range)) in genome.iter_mut().zip(&GENES).take(evolved) {
+        if rng.gen::<f32>() < rate {
+            let span = range.end() - range.start();
+            *gene =
+                (*gene + rng.gen_range(-size..=size) * span).clamp(*range.start(), *range.end());
+        }
+    }
+}
+
+fn genes(preset: &Preset) -> Genome {
+    let (prey, predators) = (&preset.prey, &preset.predators);
+    [
+        preset.alignment,
+        preset.cohesion,
+        preset.separation,
+        preset.max_force,
+        prey.min_speed,
+        prey.max_speed,
+        prey.visual_range,
+        prey.protected_range,
+        predators.min_speed,
+        predators.max_speed,
+        predators.visual_range,
+        predators.protected_range,
+    ]
+}
+
+// `base` with its settings replaced by `genome`. Counts, neighbourhoods and
+// the theme aren't evolved.
+fn with_genes(base: &Preset, genome: &Genome) -> Preset {
+    let mut preset = base.clone();
+    preset.alignment = genome[0];
+    preset.cohesion = genome[1];
+    preset.separation = genome[2];
+    preset.max_force = genome[3];
+    for (species, genes) in [
+        (&mut preset.prey, &genome[4..8]),
+        (&mut preset.predators, &genome[8..12]),
+    ] {
+        species.min_speed = genes[0];
+        species.max_speed = genes[1];
+        species.visual_range = genes[2];
+        species.protected_range = genes[3];
+    }
+    preset
+}
@@ -0,0 +1,312 @@
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use nannou::prelude::*;
+
+use crate::{
+    model::{arg, RATE_RANGE},
+    simulation::{Catch, Simulation},
+};
+
+// Bundles are kept under a typical network MTU so they arrive in one piece.
+const MAX_PACKET: usize = 1400;
+// An OSC time tag meaning "as soon as it arrives".
+const IMMEDIATELY: u64 = 1;
+
+// One OSC argument.
+#[derive(Debug, PartialEq)]
+enum Arg {
+    Int(i32),
+    Float(f32),
+}
+
+// Sends the flock to music and visuals software over Open Sound Control.
+//
+// State goes out at `rate` times a second (or every frame without a rate):
+//
+//   /flock/count         i      prey count
+//   /flock/centroid      f f    mean position
+//   /flock/spread        f      root mean square distance from the centroid,
+//                               in the same units as positions
+//   /flock/polarisation  f      0 (every which way) to 1 (all heading the same way)
+//   /prey                i f f f f   index, x, y, vx, vy
+//   /predator            i f f f f   index, x, y, vx, vy
+//
+// Catches are sent as soon as they happen, whatever the rate:
+//
+//   /catch               i i f f     predator, prey, x, y
+//
+// Positions run from 0 to 1 across the world, left to right and bottom to top.
+// Velocities are in points a step.
+pub struct OscSender {
+    socket: UdpSocket,
+    target: String,
+    interval: Option<Duration>,
+    last_sent: Option<Instant>,
+}
+
+impl OscSender {
+    // Sends to `--osc <host:port>`, at `--osc-rate <per second>` if given.
+    pub fn from_args() -> Option<OscSender> {
+        let target = arg("--osc")?;
+        let rate = arg("--osc-rate").and_then(|rate| {
+            rate.parse::<f32>()
+                .ok()
+                .filter(|rate| RATE_RANGE.contains(rate))
+                .or_else(|| {
+                    eprintln!("Ignoring OSC rate {rate:?}, sending every frame instead");
+                    None
+                })
+        });
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .and_then(|socket| socket.connect(&target).map(|()| socket))
+            .map_err(|e| eprintln!("Not sending OSC to {target}: {e}"))
+            .ok()?;
+        println!("Sending OSC to {target}");
+        Some(OscSender {
+            socket,
+            target,
+            interval: rate.map(|rate| Duration::from_secs_f32(1.0 / rate)),
+            last_sent: None,
+        })
+    }
+
+    pub fn status(&self) -> String {
+        match self.interval {
+            Some(interval) => format!(
+                "OSC: {} at {:.0}/s",
+                self.target,
+                1.0 / interval.as_secs_f32()
+            ),
+            None => format!("OSC: {}", self.target),
+        }
+    }
+
+    // Sends any new catches, then the whole flock if it's due.
+    pub fn send(&mut self, sim: &Simulation, world: Rect, catches: &[Catch]) {
+        let mut bundle = Bundle {
+            socket: &self.socket,
+            packet: Vec::new(),
+        };
+        for catch in catches {
+            let position = normalise(catch.position, world);
+            bundle.add(
+                "/catch",
+                &[
+                    index(catch.predator),
+                    index(catch.prey),
+                    Arg::Float(position.x),
+                    Arg::Float(position.y),
+                ],
+            );
+        }
+
+        let now = Instant::now();
+        let due = match (self.interval, self.last_sent) {
+            (Some(interval), Some(last_sent)) => now.duration_since(last_sent) >= interval,
+            _ => true,
+        };
+        if due {
+            self.last_sent = Some(now);
+            add_state(&mut bundle, sim, world);
+        }
+        bundle.flush();
+    }
+}
+
+fn add_state(bundle: &mut Bundle, sim: &Simulation, world: Rect) {
+    let shape = sim.shape();
+    let centroid = normalise(shape.centroid, world);
+
+    bundle.add("/flock/count", &[index(sim.flock.len())]);
+    bundle.add(
+        "/flock/centroid",
+        &[Arg::Float(centroid.x), Arg::Float(centroid.y)],
+    );
+    // Spread is scaled along each axis like positions are, so it's in the same units.
+    let spread = (shape.spread / world.wh()).length();
+    bundle.add("/flock/spread", &[Arg::Float(spread)]);
+    bundle.add("/flock/polarisation", &[Arg::Float(shape.polarisation)]);
+    for (address, boids) in [("/prey", &sim.flock), ("/predator", &sim.predators)] {
+        for (i, boid) in boids.iter().enumerate() {
+            let position = normalise(boid.position, world);
+            bundle.add(
+                address,
+                &[
+                    index(i),
+                    Arg::Float(position.x),
+                    Arg::Float(position.y),
+                    Arg::Float(boid.velocity.x),
+                    Arg::Float(boid.velocity.y),
+                ],
+            );
+        }
+    }
+}
+
+// Messages waiting to go out together, sent whenever adding another would
+// make the packet too big.
+struct Bundle<'a> {
+    socket: &'a UdpSocket,
+    packet: Vec<u8>,
+}
+
+impl Bundle<'_> {
+    fn add(&mut self, address: &str, args: &[Arg]) {
+        let message = encode_message(address, args);
+        if !self.packet.is_empty() && self.packet.len() + 4 + message.len() > MAX_PACKET {
+            self.flush();
+        }
+        if self.packet.is_empty() {
+            push_string(&mut self.packet, "#bundle");
+            self.packet.extend(IMMEDIATELY.to_be_bytes());
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        self.packet.extend((message.len() as i32).to_be_bytes());
+        self.packet.extend(message);
+    }
+
+    // Nobody may be listening yet, so failures to send are ignored.
+    fn flush(&mut self) {
+        if !self.packet.is_empty() {
+            let _ = self.socket.send(&self.packet);
+            self.packet.clear();
+        }
+    }
+}
+
+fn encode_message(address: &str, args: &[Arg]) -> Vec<u8> {
+    let mut message = Vec::new();
+    push_string(&mut message, address);
+    let tags: String = std::iter::once(',')
+        .chain(args.iter().map(|arg| match arg {
+            Arg::Int(_) => 'i',
+            Arg::Float(_) => 'f',
+        }))
+        .collect();
+    push_string(&mut message, &tags);
+    for arg in args {
+        match arg {
+            Arg::Int(value) => message.extend(value.to_be_bytes()),
+            Arg::Float(value) => message.extend(value.to_be_bytes()),
+        }
+    }
+    message
+}
+
+// OSC strings end with at least one null, padded to a multiple of four bytes.
+fn push_string(packet: &mut Vec<u8>, text: &str) {
+    packet.extend(text.as_bytes());
+    let padding = 4 - text.len() % 4;
+    packet.extend(std::iter::repeat_n(0, padding));
+}
+
+fn index(i: usize) -> Arg {
+    Arg::Int(i32::try_from(i).unwrap_or(i32::MAX))
+}
+
+fn normalise(position: Vec2, world: Rect) -> Vec2 {
+    (position - world.bottom_left()) / world.wh()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads a padded string starting at `at`, checking the padding on the way.
+    fn read_string(bytes: &[u8], at: &mut usize) -> String {
+        assert_eq!(*at % 4, 0, "strings start on a four byte boundary");
+        let end = *at + bytes[*at..].iter().position(|&b| b == 0).unwrap();
+        let text = String::from_utf8(bytes[*at..end].to_vec()).unwrap();
+        let padded = (end + 1).next_multiple_of(4);
+        assert!(bytes[end..padded].iter().all(|&b| b == 0));
+        *at = padded;
+        text
+    }
+
+    fn read_word(bytes: &[u8], at: &mut usize) -> [u8; 4] {
+        let word = bytes[*at..*at + 4].try_into().unwrap();
+        *at += 4;
+        word
+    }
+
+    fn decode_message(bytes: &[u8]) -> (String, Vec<Arg>) {
+        let mut at = 0;
+        let address = read_string(bytes, &mut at);
+        let tags = read_string(bytes, &mut at);
+        let args = tags
+            .strip_prefix(',')
+            .unwrap()
+            .chars()
+            .map(|tag| match tag {
+                'i' => Arg::Int(i32::from_be_bytes(read_word(bytes, &mut at))),
+                'f' => Arg::Float(f32::from_be_bytes(read_word(bytes, &mut at))),
+                _ => panic!("unexpected type tag {tag}"),
+            })
+            .collect();
+        assert_eq!(at, bytes.len(), "nothing follows the arguments");
+        (address, args)
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let args = [Arg::Int(-3), Arg::Float(0.25), Arg::Int(i32::MAX)];
+        let message = encode_message("/prey", &args);
+        assert_eq!(message.len() % 4, 0);
+        assert_eq!(decode_message(&message), ("/prey".to_string(), args.into()));
+    }
+
+    #[test]
+    fn strings_always_end_in_a_null() {
+        for (text, size) in [("", 4), ("abc", 4), ("abcd", 8), ("#bundle", 8)] {
+            let mut bytes = Vec::new();
+            push_string(&mut bytes, text);
+            assert_eq!(bytes.len(), size, "{text:?}");
+            assert_eq!(read_string(&bytes, &mut 0), text);
+        }
+    }
+
+    #[test]
+    fn bundles_stay_under_the_packet_size() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(receiver.local_addr().unwrap()).unwrap();
+
+        let mut bundle = Bundle {
+            socket: &socket,
+            packet: Vec::new(),
+        };
+        let count = 200;
+        for i in 0..count {
+            bundle.add("/prey", &[index(i), Arg::Float(0.5), Arg::Float(0.5)]);
+        }
+        bundle.flush();
+
+        let mut received = 0;
+        let mut packet = [0; 2 * MAX_PACKET];
+        while received < count {
+            let size = receiver.recv(&mut packet).unwrap();
+            assert!(size <= MAX_PACKET, "a {size} byte packet");
+            let packet = &packet[..size];
+            let mut at = 0;
+            assert_eq!(read_string(packet, &mut at), "#bundle");
+            assert_eq!(packet[at..at + 8], IMMEDIATELY.to_be_bytes());
+            at += 8;
+            while at < size {
+                let length = i32::from_be_bytes(read_word(packet, &mut at)) as usize;
+                let (address, args) = decode_message(&packet[at..at + length]);
+                assert_eq!(address, "/prey");
+                assert_eq!(args[0], index(received), "messages arrive in order");
+                received += 1;
+                at += length;
+            }
+            assert_eq!(at, size);
+        }
+        assert_eq!(received, count);
+    }
+}
@@ -0,0 +1,105 @@
+use nannou::prelude::*;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PathMode {
+    Off,
+    Polyline,
+    Spline,
+}
+
+impl PathMode {
+    pub fn next(&self) -> PathMode {
+        match *self {
+            PathMode::Off => PathMode::Polyline,
+            PathMode::Polyline => PathMode::Spline,
+            PathMode::Spline => PathMode::Off,
+        }
+    }
+}
+
+pub struct Path {
+    pub points: Vec<Vec2>,
+    pub width: f32,
+    pub closed: bool,
+}
+
+impl Path {
+    // A loop around the window for boids to circulate on until the user draws their own.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new_loop(bounds: Rect) -> Path {
+        let (w, h) = (bounds.w() * 0.35, bounds.h() * 0.3);
+        let points = (0..8)
+            .map(|i| {
+                let angle = i as f32 / 8.0 * TAU;
+                bounds.xy() + vec2(angle.cos() * w, angle.sin() * h)
+            })
+            .collect();
+        Path {
+            points,
+            width: 40.0,
+            closed: true,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.closed = false;
+    }
+
+    // The path as the line segments boids actually follow, in travel order.
+    pub fn polyline(&self, mode: PathMode) -> Vec<Vec2> {
+        let mut points = match mode {
+            PathMode::Off => return Vec::new(),
+            PathMode::Polyline => self.points.clone(),
+            PathMode::Spline => self.spline(8),
+        };
+        if self.closed && points.len() > 2 {
+            points.push(points[0]);
+        }
+        points
+    }
+
+    // Catmull-Rom through every control point, `steps` samples per span.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )]
+    fn spline(&self, steps: usize) -> Vec<Vec2> {
+        let n = self.points.len();
+        if n < 3 {
+            return self.points.clone();
+        }
+        let point = |i: isize| -> Vec2 {
+            if self.closed {
+                self.points[i.rem_euclid(n as isize) as usize]
+            } else {
+                self.points[i.clamp(0, n as isize - 1) as usize]
+            }
+        };
+        let spans = if self.closed { n } else { n - 1 };
+        let mut samples = Vec::with_capacity(spans * steps + 1);
+        for span in 0..spans as isize {
+            let (p0, p1, p2, p3) = (
+                point(span - 1),
+                point(span),
+                point(span + 1),
+                point(span + 2),
+            );
+            for step in 0..steps {
+                let t = step as f32 / steps as f32;
+                let (t2, t3) = (t * t, t * t * t);
+                samples.push(
+                    0.5 * (2.0 * p1
+                        + (p2 - p0) * t
+                        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+                        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3),
+                );
+            }
+        }
+        if !self.closed {
+            samples.push(self.points[n - 1]);
+        }
+        samples
+    }
+}
@@ -0,0 +1,548 @@
+use std::{fmt::Display, ops::RangeInclusive};
+
+use crate::{
+    model::{
+        BoidOptions, Model, MAX_FORCE_RANGE, MAX_PREDATORS, MAX_PREY, MODIFIER_RANGE,
+        NEAREST_K_RANGE, PROTECTION_RANGE, SPEED_RANGE, VISION_RANGE,
+    },
+    neighbourhood::Neighbourhood,
+    simulation::Simulation,
+};
+
+// Prefix for shareable preset codes, bumped if the field order ever changes.
+const CODE_VERSION: &str = "boids1";
+
+// A named bundle of flocking weights and per-species settings.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Preset {
+    pub name: String,
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub separation: f32,
+    pub max_force: f32,
+    pub prey: Species,
+    pub predators: Species,
+    pub theme: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Species {
+    pub count: usize,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    pub visual_range: f32,
+    pub protected_range: f32,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Species {
+    fn capture(options: &BoidOptions) -> Species {
+        Species {
+            count: options.flock_size,
+            min_speed: options.min_speed,
+            max_speed: options.max_speed,
+            visual_range: options.visual_range,
+            protected_range: options.protected_range,
+            neighbourhood: options.neighbourhood,
+        }
+    }
+
+    // Returns true once every value has reached the target.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap
+    )]
+    fn blend(&self, options: &mut BoidOptions, rate: f32) -> bool {
+        options.neighbourhood = self.neighbourhood;
+        let mut done = approach(&mut options.min_speed, self.min_speed, rate);
+        done &= approach(&mut options.max_speed, self.max_speed, rate);
+        done &= approach(&mut options.visual_range, self.visual_range, rate);
+        done &= approach(&mut options.protected_range, self.protected_range, rate);
+
+        // Boids are added or removed a few at a time rather than all at once.
+        let difference = self.count as i32 - options.flock_size as i32;
+        if difference != 0 {
+            let step = (difference as f32 * rate).round() as i32;
+            options.n_mod = if step == 0 { difference.signum() } else { step };
+            done = false;
+        }
+        done
+    }
+
+    fn encode(&self) -> String {
+        let neighbourhood = match self.neighbourhood {
+            Neighbourhood::Metric => "m".to_string(),
+            Neighbourhood::Topological(k) => format!("t{k}"),
+            Neighbourhood::Hybrid(k) => format!("h{k}"),
+        };
+        format!(
+            "{},{},{},{},{},{neighbourhood}",
+            self.count,
+            short(self.min_speed),
+            short(self.max_speed),
+            short(self.visual_range),
+            short(self.protected_range),
+        )
+    }
+
+    // Checks every value is one the control panel could have set.
+    fn validate(&self, max_count: usize) -> Result<(), String> {
+        in_range("count", self.count, &(0..=max_count))?;
+        in_range("min speed", self.min_speed, &SPEED_RANGE)?;
+        in_range("max speed", self.max_speed, &SPEED_RANGE)?;
+        in_range("visual range", self.visual_range, &VISION_RANGE)?;
+        in_range("protected range", self.protected_range, &PROTECTION_RANGE)?;
+        if let Neighbourhood::Topological(k) | Neighbourhood::Hybrid(k) = self.neighbourhood {
+            in_range("nearest k", k, &NEAREST_K_RANGE)?;
+        }
+        Ok(())
+    }
+
+    fn decode(fields: &[&str]) -> Result<Species, String> {
+        let kind = fields[5].get(..1).unwrap_or_default();
+        let k = fields[5].get(1..).unwrap_or_default();
+        let neighbourhood = match kind {
+            "m" if k.is_empty() => Neighbourhood::Metric,
+            "t" => Neighbourhood::Topological(number(k)?),
+            "h" => Neighbourhood::Hybrid(number(k)?),
+            _ => return Err(format!("unknown neighbourhood {:?}", fields[5])),
+        };
+        Ok(Species {
+            count: number(fields[0])?,
+            min_speed: number(fields[1])?,
+            max_speed: number(fields[2])?,
+            visual_range: number(fields[3])?,
+            protected_range: number(fields[4])?,
+            neighbourhood,
+        })
+    }
+}
+
+impl Preset {
+    pub fn built_in() -> Vec<Preset> {
+        vec![
+            Preset::murmuration(),
+            Preset::fish_school(),
+            Preset::insect_swarm(),
+            Preset::milling_vortex(),
+            Preset::sparse_herd(),
+            Preset::predator_frenzy(),
+        ]
+    }
+
+    // A big, tightly aligned flock where each bird only tracks its nearest few.
+    pub fn murmuration() -> Preset {
+        Preset {
+            name: "Murmuration".to_string(),
+            alignment: 0.6,
+            cohesion: 0.35,
+            separation: 0.7,
+            max_force: 0.15,
+            prey: Species {
+                count: 600,
+                min_speed: 3.0,
+                max_speed: 5.0,
+                visual_range: 70.0,
+                protected_range: 20.0,
+                neighbourhood: Neighbourhood::Topological(Neighbourhood::DEFAULT_K),
+            },
+            predators: Species {
+                count: 1,
+                min_speed: 3.0,
+                max_speed: 4.0,
+                visual_range: 120.0,
+                protected_range: 30.0,
+                neighbourhood: Neighbourhood::Metric,
+            },
+            theme: "Grey".to_string(),
+        }
+    }
+
+    pub fn fish_school() -> Preset {
+        Preset {
+            name: "Fish School".to_string(),
+            alignment: 0.8,
+            cohesion: 0.5,
+            separation: 0.6,
+            max_force: 0.25,
+            prey: Species {
+                count: 300,
+                min_speed: 2.0,
+                max_speed: 3.5,
+                visual_range: 60.0,
+                protected_range: 15.0,
+                neighbourhood: Neighbourhood::Hybrid(6),
+            },
+            predators: Species {
+                count: 1,
+                min_speed: 2.0,
+                max_speed: 3.5,
+                visual_range: 120.0,
+                protected_range: 30.0,
+                neighbourhood: Neighbourhood::Metric,
+            },
+            theme: "DeepSea".to_string(),
+        }
+    }
+
+    // Strong cohesion with almost no alignment, so they buzz around a shared centre.
+    pub fn insect_swarm() -> Preset {
+        Preset {
+            name: "Insect Swarm".to_string(),
+            alignment: 0.05,
+            cohesion: 0.9,
+            separation: 0.4,
+            max_force: 0.6,
+            prey: Species {
+                count: 400,
+                min_speed: 1.5,
+                max_speed: 6.0,
+                visual_range: 50.0,
+                protected_range: 10.0,
+                neighbourhood: Neighbourhood::Metric,
+            },
+            predators: Species {
+                count: 0,
+                ..Preset::default_predators()
+            },
+            theme: "Normal".to_string(),
+        }
+    }
+
+    // Near-constant speed and weak steering make the flock circle rather than travel.
+    pub fn milling_vortex() -> Preset {
+        Preset {
+            name: "Milling Vortex".to_string(),
+            alignment: 1.0,
+            cohesion: 0.8,
+            separation: 0.5,
+            max_force: 0.1,
+            prey: Species {
+                count: 250,
+                min_speed: 3.0,
+                max_speed: 3.5,
+                visual_range: 120.0,
+                protected_range: 20.0,
+                neighbourhood: Neighbourhood::Metric,
+            },
+            predators: Species {
+                count: 0,
+                ..Preset::default_predators()
+            },
+            theme: "ColourBlind".to_string(),
+        }
+    }
+
+    pub fn sparse_herd() -> Preset {
+        Preset {
+            name: "Sparse Herd".to_string(),
+            alignment: 0.3,
+            cohesion: 0.15,
+            separation: 1.2,
+            max_force: 0.1,
+            prey: Species {
+                count: 60,
+                min_speed: 1.0,
+                max_speed: 2.0,
+                visual_range: 150.0,
+                protected_range: 50.0,
+                neighbourhood: Neighbourhood::Metric,
+            },
+            predators: Species {
+                count: 1,
+                min_speed: 1.5,
+                max_speed: 2.5,
+                ..Preset::default_predators()
+            },
+            theme: "Normal".to_string(),
+        }
+    }
+
+    pub fn predator_frenzy() -> Preset {
+        Preset {
+            name: "Predator Frenzy".to_string(),
+            alignment: 0.4,
+            cohesion: 0.3,
+            separation: 0.9,
+            max_force: 0.4,
+            prey: Species {
+                count: 300,
+                min_speed: 2.5,
+                max_speed: 5.0,
+                visual_range: 80.0,
+                protected_range: 25.0,
+                neighbourhood: Neighbourhood::Metric,
+            },
+            predators: Species {
+                count: 6,
+                min_speed: 3.0,
+                max_speed: 4.5,
+                visual_range: 150.0,
+                protected_range: 30.0,
+                neighbourhood: Neighbourhood::Metric,
+            },
+            theme: "HighContrast".to_string(),
+        }
+    }
+
+    fn default_predators() -> Species {
+        Species {
+            count: 1,
+            min_speed: 2.0,
+            max_speed: 3.0,
+            visual_range: 100.0,
+            protected_range: 30.0,
+            neighbourhood: Neighbourhood::Metric,
+        }
+    }
+
+    // The active simulation's current settings, so they can be shared.
+    pub fn capture(model: &Model) -> Preset {
+        Preset::of(model.sim(), &model.theme().name)
+    }
+
+    pub fn of(sim: &Simulation, theme: &str) -> Preset {
+        Preset {
+            name: "Custom".to_string(),
+            alignment: sim.boid_options.steering.weight("Alignment"),
+            cohesion: sim.boid_options.steering.weight("Cohesion"),
+            separation: sim.boid_options.steering.weight("Separation"),
+            max_force: sim.max_force,
+            prey: Species::capture(&sim.boid_options),
+            predators: Species::capture(&sim.predator_options),
+            theme: theme.to_string(),
+        }
+    }
+
+    // Moves the active simulation `rate` of the way towards this preset, returning
+    // true once it's there. A rate of 1.0 switches over immediately.
+    pub fn blend(&self, model: &mut Model, rate: f32) -> bool {
+        if let Some(index) = model.themes.iter().position(|t| t.name == self.theme) {
+            model.theme_index = index;
+        }
+        self.blend_into(model.sim_mut(), rate)
+    }
+
+    // Like `blend`, for a simulation on its own. Leaves the theme alone.
+    pub fn blend_into(&self, sim: &mut Simulation, rate: f32) -> bool {
+        let mut done = approach(&mut sim.max_force, self.max_force, rate);
+        for (behaviour, target) in [
+            ("Alignment", self.alignment),
+            ("Cohesion", self.cohesion),
+            ("Separation", self.separation),
+        ] {
+            if let Some(weight) = sim.boid_options.steering.weight_mut(behaviour) {
+                done &= approach(weight, target, rate);
+            }
+        }
+        done &= self.prey.blend(&mut sim.boid_options, rate);
+        done &= self.predators.blend(&mut sim.predator_options, rate);
+        done
+    }
+
+    // A short, comma separated code that `decode` turns back into the same preset.
+    pub fn encode(&self) -> String {
+        format!(
+            "{CODE_VERSION},{},{},{},{},{},{},{}",
+            short(self.alignment),
+            short(self.cohesion),
+            short(self.separation),
+            short(self.max_force),
+            self.prey.encode(),
+            self.predators.encode(),
+            self.theme,
+        )
+    }
+
+    // A code, or the name of a file holding one, like the optimiser writes.
+    pub fn load(code_or_file: &str) -> Result<Preset, String> {
+        let code = std::fs::read_to_string(code_or_file.trim())
+            .unwrap_or_else(|_| code_or_file.to_string());
+        Preset::decode(&code)
+    }
+
+    pub fn decode(code: &str) -> Result<Preset, String> {
+        // The theme name goes last, so it's free to contain commas.
+        let fields: Vec<&str> = code.trim().splitn(18, ',').map(str::trim).collect();
+        if fields[0] != CODE_VERSION {
+            return Err(format!(
+                "preset codes should start with \"{CODE_VERSION},\""
+            ));
+        }
+        if fields.len() != 18 {
+            return Err(format!("expected 18 fields, found {}", fields.len()));
+        }
+        let preset = Preset {
+            name: "Custom".to_string(),
+            alignment: number(fields[1])?,
+            cohesion: number(fields[2])?,
+            separation: number(fields[3])?,
+            max_force: number(fields[4])?,
+            prey: Species::decode(&fields[5..11])?,
+            predators: Species::decode(&fields[11..17])?,
+            theme: fields[17].to_string(),
+        };
+        preset.validate()?;
+        Ok(preset)
+    }
+
+    // Same limits as the control panel, so a code can't ask for an unplayable flock.
+    pub fn validate(&self) -> Result<(), String> {
+        in_range("alignment", self.alignment, &MODIFIER_RANGE)?;
+        in_range("cohesion", self.cohesion, &MODIFIER_RANGE)?;
+        in_range("separation", self.separation, &MODIFIER_RANGE)?;
+        in_range("max force", self.max_force, &MAX_FORCE_RANGE)?;
+        self.prey
+            .validate(MAX_PREY)
+            .map_err(|e| format!("prey {e}"))?;
+        self.predators
+            .validate(MAX_PREDATORS)
+            .map_err(|e| format!("predator {e}"))
+    }
+}
+
+// Eases `value` towards `target`, snapping once it's close enough to count as there.
+fn approach(value: &mut f32, target: f32, rate: f32) -> bool {
+    *value += (target - *value) * rate.clamp(0.0, 1.0);
+    if (target - *value).abs() < 0.001 {
+        *value = target;
+        return true;
+    }
+    false
+}
+
+// NaN and the infinities are never inside a range, so this rejects those too.
+pub fn in_range<T: PartialOrd + Display>(
+    name: &str,
+    value: T,
+    range: &RangeInclusive<T>,
+) -> Result<(), String> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name} should be from {} to {}, not {value}",
+            range.start(),
+            range.end()
+        ))
+    }
+}
+
+fn number<T: std::str::FromStr>(field: &str) -> Result<T, String> {
+    field
+        .parse()
+        .map_err(|_| format!("{field:?} isn't a valid number"))
+}
+
+// Up to three decimal places, without trailing zeros, to keep codes short.
+fn short(value: f32) -> String {
+    let text = format!("{value:.3}");
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code() -> String {
+        Preset::murmuration().encode()
+    }
+
+    // The code with field `index` swapped for `value`.
+    fn with_field(index: usize, value: &str) -> String {
+        let mut fields: Vec<String> = code().split(',').map(str::to_string).collect();
+        fields[index] = value.to_string();
+        fields.join(",")
+    }
+
+    #[test]
+    fn built_in_presets_round_trip() {
+        for preset in Preset::built_in() {
+            let decoded = Preset::decode(&preset.encode()).unwrap();
+            assert_eq!(
+                decoded,
+                Preset {
+                    name: "Custom".to_string(),
+                    ..preset
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn theme_may_contain_commas() {
+        let preset = Preset {
+            theme: "Dusk, Late".to_string(),
+            ..Preset::fish_school()
+        };
+        assert_eq!(
+            Preset::decode(&preset.encode()).unwrap().theme,
+            "Dusk, Late"
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        assert!(Preset::decode(&code().replacen("boids1", "boids2", 1)).is_err());
+        assert!(Preset::decode("").is_err());
+        assert!(Preset::decode("not a code").is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_field_counts() {
+        let code = code();
+        let fields: Vec<&str> = code.split(',').collect();
+        let short = fields[..17].join(",");
+        assert!(Preset::decode(&short).is_err());
+        assert!(Preset::decode("boids1,0.5").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_neighbourhoods() {
+        // Field 10 is the prey's neighbourhood.
+        for bad in ["x", "t", "h", "m3", "tk", "t-1", ""] {
+            assert!(Preset::decode(&with_field(10, bad)).is_err(), "{bad:?}");
+        }
+        for good in ["m", "t1", "h30"] {
+            assert!(Preset::decode(&with_field(10, good)).is_ok(), "{good:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        for (index, bad) in [
+            (1, "NaN"),
+            (1, "2.5"),
+            (2, "-0.1"),
+            (4, "inf"),
+            (4, "0"),
+            (5, "4000000000"),
+            (5, "1001"),
+            (6, "NaN"),
+            (7, "-3"),
+            (7, "1e30"),
+            (8, "0"),
+            (9, "500"),
+            (10, "t0"),
+            (10, "h31"),
+            (11, "7"),
+            (13, "inf"),
+        ] {
+            assert!(
+                Preset::decode(&with_field(index, bad)).is_err(),
+                "field {index} = {bad:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_the_limits() {
+        for (index, value) in [(1, "0"), (1, "2"), (5, "1000"), (11, "6"), (7, "8")] {
+            assert!(
+                Preset::decode(&with_field(index, value)).is_ok(),
+                "field {index} = {value:?}"
+            );
+        }
+    }
+}
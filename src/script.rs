@@ -0,0 +1,266 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use nannou::prelude::*;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::{
+    boids::Boid,
+    steering::{Context, Registry, SteeringBehavior},
+};
+
+// How much work one call of a script may do before it's stopped, so a runaway
+// loop costs a few milliseconds rather than the whole simulation.
+const MAX_OPERATIONS: u64 = 100_000;
+// How long a script may take over a whole step, across every boid it steers.
+// Many calls that each stay under the operation limit can still add up to a
+// frozen frame. The example script takes about half this with a thousand prey,
+// in a release build.
+const STEP_TIME: Duration = Duration::from_millis(50);
+// The clock is only read this often while a script runs, since reading it
+// costs more than most operations.
+const OPERATIONS_PER_CHECK: u64 = 256;
+
+// A steering behaviour written in Rhai, loaded from `scripts/<name>.rhai` and
+// reloaded whenever the file changes.
+//
+// The script sees `boid`, `neighbours`, `close`, `predators` and `world`, and
+// its last expression is the force, either `[x, y]` or `#{x: .., y: ..}`.
+pub struct ScriptBehavior {
+    name: &'static str,
+    path: PathBuf,
+    engine: Engine,
+    script: RwLock<Script>,
+    budget: Arc<Budget>,
+}
+
+// When this step's time runs out, shared with the engine so it can stop a
+// script part way through.
+struct Budget {
+    epoch: Instant,
+    // Nanoseconds after `epoch`.
+    deadline: AtomicU64,
+}
+
+impl Budget {
+    fn start(&self) {
+        let deadline = self.epoch.elapsed() + STEP_TIME;
+        let nanos = u64::try_from(deadline.as_nanos()).unwrap_or(u64::MAX);
+        self.deadline.store(nanos, Ordering::Relaxed);
+    }
+
+    fn spent(&self) -> bool {
+        let now = u64::try_from(self.epoch.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        now > self.deadline.load(Ordering::Relaxed)
+    }
+}
+
+struct Script {
+    ast: Option<AST>,
+    modified: Option<SystemTime>,
+    // Set on the first runtime error, after which the script isn't run again
+    // until it's saved. Otherwise a script that hits the operation or time
+    // limit would do so for every boid, every step.
+    failed: bool,
+}
+
+impl ScriptBehavior {
+    fn new(path: &Path) -> ScriptBehavior {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let budget = Arc::new(Budget {
+            epoch: Instant::now(),
+            deadline: AtomicU64::new(u64::MAX),
+        });
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let progress = Arc::clone(&budget);
+        engine.on_progress(move |operations| {
+            (operations % OPERATIONS_PER_CHECK == 0 && progress.spent()).then_some(Dynamic::UNIT)
+        });
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(1_000);
+        engine.set_max_array_size(10_000);
+        engine.set_max_map_size(1_000);
+        let behaviour = ScriptBehavior {
+            // Scripts are loaded once at startup, so this only leaks a few names.
+            name: Box::leak(name.into_boxed_str()),
+            path: path.to_path_buf(),
+            engine,
+            script: RwLock::new(Script {
+                ast: None,
+                modified: None,
+                failed: false,
+            }),
+            budget,
+        };
+        behaviour.reload();
+        behaviour
+    }
+
+    // Compiles the script again if it's been saved since it was last loaded. A
+    // script that doesn't compile is reported and leaves the boids unaffected.
+    fn reload(&self) {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let mut script = self.script.write().expect("script lock poisoned");
+        if modified.is_none() || modified == script.modified {
+            return;
+        }
+        script.modified = modified;
+        script.failed = false;
+        script.ast = match self.engine.compile_file(self.path.clone()) {
+            Ok(ast) => {
+                println!("Loaded script {}", self.path.display());
+                Some(ast)
+            }
+            Err(e) => {
+                eprintln!("Error in script {}: {e}", self.path.display());
+                None
+            }
+        };
+    }
+
+    fn run(&self, ast: &AST, context: &Context) -> Result<Vec2, String> {
+        let out_of_time = || format!("it took more than {STEP_TIME:?} in one step");
+        if self.budget.spent() {
+            return Err(out_of_time());
+        }
+        let env = context.env;
+        let mut world = Map::new();
+        world.insert("left".into(), env.bounds.left().into());
+        world.insert("right".into(), env.bounds.right().into());
+        world.insert("bottom".into(), env.bounds.bottom().into());
+        world.insert("top".into(), env.bounds.top().into());
+        world.insert("cursor_x".into(), env.cursor.x.into());
+        world.insert("cursor_y".into(), env.cursor.y.into());
+        world.insert(
+            "cursor_mode".into(),
+            format!("{:?}", env.cursor_mode).into(),
+        );
+
+        let mut scope = Scope::new();
+        scope.push_constant("boid", boid_map(context.boid));
+        scope.push_constant("neighbours", boid_array(context.nearby.iter().copied()));
+        scope.push_constant("close", boid_array(context.close.iter().copied()));
+        scope.push_constant("predators", boid_array(context.predators.iter()));
+        scope.push_constant("world", world);
+
+        let result: Dynamic =
+            self.engine
+                .eval_ast_with_scope(&mut scope, ast)
+                .map_err(|e| match *e {
+                    EvalAltResult::ErrorTerminated(..) => out_of_time(),
+                    e => e.to_string(),
+                })?;
+        let force = to_force(&result).ok_or_else(|| {
+            format!(
+                "expected [x, y] or #{{x: .., y: ..}}, got {}",
+                result.type_name()
+            )
+        })?;
+        // One infinite or NaN force would spread to every boid nearby.
+        if force.is_finite() {
+            Ok(force)
+        } else {
+            Err(format!("the force {force} isn't a finite number"))
+        }
+    }
+}
+
+impl SteeringBehavior for ScriptBehavior {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        let script = self.script.read().expect("script lock poisoned");
+        let Some(ast) = script.ast.as_ref().filter(|_| !script.failed) else {
+            return Vec2::ZERO;
+        };
+        match self.run(ast, context) {
+            Ok(force) => force,
+            Err(e) => {
+                drop(script);
+                let mut script = self.script.write().expect("script lock poisoned");
+                // Another thread may have got here first.
+                if !script.failed {
+                    eprintln!("Error running script {}: {e}", self.path.display());
+                    eprintln!("It's switched off until the file is saved again.");
+                    script.failed = true;
+                }
+                Vec2::ZERO
+            }
+        }
+    }
+
+    fn refresh(&self) {
+        self.reload();
+    }
+
+    fn start_step(&self) {
+        self.budget.start();
+    }
+}
+
+// Registers every `*.rhai` file in `dir`, named after the file.
+pub fn load_all(registry: &mut Registry, dir: impl AsRef<Path>) {
+    let Ok(entries) = std::fs::read_dir(dir.as_ref()) else {
+        return;
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        registry.register(ScriptBehavior::new(&path));
+    }
+}
+
+fn boid_map(boid: &Boid) -> Map {
+    let mut map = Map::new();
+    map.insert("x".into(), boid.position.x.into());
+    map.insert("y".into(), boid.position.y.into());
+    map.insert("vx".into(), boid.velocity.x.into());
+    map.insert("vy".into(), boid.velocity.y.into());
+    map.insert("max_speed".into(), boid.max_speed.into());
+    map.insert("max_force".into(), boid.max_force.into());
+    map.insert("visual_range".into(), boid.visual_range.into());
+    map.insert("protected_range".into(), boid.protected_range.into());
+    map.insert("leader".into(), boid.leader.is_some().into());
+    map
+}
+
+fn boid_array<'a>(boids: impl Iterator<Item = &'a Boid>) -> Array {
+    boids.map(|boid| boid_map(boid).into()).collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn to_force(result: &Dynamic) -> Option<Vec2> {
+    let number = |value: &Dynamic| {
+        value
+            .as_float()
+            .ok()
+            .or_else(|| value.as_int().ok().map(|i| i as f32))
+    };
+    if let Some(array) = result.read_lock::<Array>() {
+        return match array.as_slice() {
+            [x, y] => Some(vec2(number(x)?, number(y)?)),
+            _ => None,
+        };
+    }
+    let map = result.read_lock::<Map>()?;
+    Some(vec2(number(map.get("x")?)?, number(map.get("y")?)?))
+}
@@ -0,0 +1,633 @@
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use nannou::prelude::*;
+
+use crate::{
+    boids::{Boid, BoidType},
+    cursor::CursorMode,
+    model::{arg, Model},
+    simulation::{Environment, Simulation},
+};
+
+// A client that can't take a frame this quickly is dropped. Frames are written
+// on a thread per client, so a slow one never holds up the host or anyone else.
+// Clients give up on a host that won't take their cursor in this time, too,
+// rather than freezing while they wait.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+// Frames waiting for a client. Each holds the whole flock, so a client that
+// falls behind just skips to the newest rather than queueing more.
+const QUEUED_FRAMES: usize = 2;
+// What a client sends: its cursor as x and y, then its mode.
+const CURSOR_MESSAGE: usize = 9;
+// Per cursor in a frame: an id, then the same as a client sends.
+const FRAME_CURSOR: usize = 1 + CURSOR_MESSAGE;
+// Per boid in a frame: position and velocity, each squeezed into two i16s.
+const FRAME_BOID: usize = 8;
+// Velocities are sent in 1/1024ths of a point a step.
+const VELOCITY_SCALE: f32 = 1024.0;
+const I16_MAX: f32 = i16::MAX as f32;
+// The biggest frame a host can send: the world's size, as many cursors and
+// boids as the counts allow, and the counts themselves. Anything claiming to be
+// longer isn't a frame, and the connection is dropped rather than trusting it.
+const MAX_FRAME: usize =
+    8 + 1 + u8::MAX as usize * FRAME_CURSOR + 4 + 2 * u16::MAX as usize * FRAME_BOID;
+
+// Several instances sharing one flock over the network. The host runs the
+// simulation and every client's cursor pushes its boids around. Each frame the
+// host sends everyone the boids' state, which clients show in place of their own.
+//
+// Clients open a TCP connection to the host and are sent an id byte. After
+// that, clients send their cursor whenever it's drawn, and the host sends a
+// frame after each update: a u32 length, then
+//
+//   world width and height          f32, f32
+//   cursor count                    u8
+//   each cursor                     id u8, x f32, y f32, mode u8
+//   prey count, predator count      u16, u16
+//   each boid                       x i16, y i16, vx i16, vy i16
+//
+// Positions are fractions of the world's half-size, velocities are scaled by
+// `VELOCITY_SCALE`, and everything is little endian.
+pub enum Session {
+    Host(Host),
+    Client(Client),
+}
+
+pub struct Host {
+    listener: TcpListener,
+    port: u16,
+    peers: Vec<Peer>,
+    next_id: u8,
+}
+
+struct Peer {
+    id: u8,
+    // Frames for the writing thread to send.
+    frames: SyncSender<Arc<[u8]>>,
+    cursor: Arc<Mutex<Option<(Vec2, CursorMode)>>>,
+    connected: Arc<AtomicBool>,
+}
+
+pub struct Client {
+    stream: TcpStream,
+    address: String,
+    id: u8,
+    // The newest frame from the host, taken once it's been shown.
+    latest: Arc<Mutex<Option<Snapshot>>>,
+    connected: Arc<AtomicBool>,
+    // Everyone else's cursors, from the last frame shown.
+    cursors: Vec<(Vec2, CursorMode)>,
+}
+
+struct Snapshot {
+    world: Vec2,
+    cursors: Vec<(u8, Vec2, CursorMode)>,
+    prey: Vec<(Vec2, Vec2)>,
+    predators: Vec<(Vec2, Vec2)>,
+}
+
+impl Session {
+    // Hosts with `--host <port>`, or joins with `--join <address:port>`.
+    pub fn from_args() -> Option<Session> {
+        if let Some(port) = arg("--host") {
+            let Ok(port) = port.parse::<u16>() else {
+                eprintln!("Not hosting: {port:?} isn't a port number");
+                return None;
+            };
+            return Host::start(port)
+                .map(Session::Host)
+                .map_err(|e| eprintln!("Couldn't host on port {port}: {e}"))
+                .ok();
+        }
+        let address = arg("--join")?;
+        Client::connect(&address)
+            .map(Session::Client)
+            .map_err(|e| eprintln!("Couldn't join {address}: {e}"))
+            .ok()
+    }
+
+    // Whether the boids come from the host, so this instance shouldn't step them.
+    pub fn following(&self) -> bool {
+        matches!(self, Session::Client(_))
+    }
+
+    // Other people's cursors, in world coordinates.
+    pub fn cursors(&self) -> Vec<(Vec2, CursorMode)> {
+        match self {
+            Session::Host(host) => host
+                .peers
+                .iter()
+                .filter_map(|peer| *peer.cursor.lock().expect("cursor lock poisoned"))
+                .collect(),
+            Session::Client(client) => client.cursors.clone(),
+        }
+    }
+
+    pub fn status(&self) -> String {
+        match self {
+            Session::Host(host) => format!(
+                "Hosting on port {} ({} joined)",
+                host.port,
+                host.peers.len()
+            ),
+            Session::Client(client) => format!("Joined {} as user {}", client.address, client.id),
+        }
+    }
+}
+
+impl Host {
+    fn start(port: u16) -> std::io::Result<Host> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        println!("Hosting on port {port}. Others can join with --join <this machine>:{port}");
+        Ok(Host {
+            listener,
+            port,
+            peers: Vec::new(),
+            next_id: 1,
+        })
+    }
+
+    // Welcomes anyone waiting to join, and lets go of anyone who's left.
+    fn accept(&mut self) {
+        self.peers
+            .retain(|peer| peer.connected.load(Ordering::Relaxed));
+        while let Ok((stream, address)) = self.listener.accept() {
+            let taken: Vec<u8> = self.peers.iter().map(|peer| peer.id).collect();
+            // Dropping the stream turns them away.
+            let Some(id) = free_id(self.next_id, &taken) else {
+                eprintln!("Turning {address} away: all {} places are taken", u8::MAX);
+                continue;
+            };
+            match Peer::start(stream, id) {
+                Ok(peer) => {
+                    println!("User {id} joined from {address}");
+                    self.next_id = id.wrapping_add(1).max(1);
+                    self.peers.push(peer);
+                }
+                Err(e) => eprintln!("Couldn't welcome {address}: {e}"),
+            }
+        }
+    }
+
+    fn broadcast(&mut self, sim: &Simulation, env: &Environment) {
+        if self.peers.is_empty() {
+            return;
+        }
+        let mut cursors = vec![(0, env.cursor, env.cursor_mode)];
+        for peer in &self.peers {
+            if let Some((position, mode)) = *peer.cursor.lock().expect("cursor lock poisoned") {
+                cursors.push((peer.id, position, mode));
+            }
+        }
+        let frame: Arc<[u8]> = encode_frame(sim, env.bounds, &cursors).into();
+        for peer in &self.peers {
+            match peer.frames.try_send(Arc::clone(&frame)) {
+                Ok(()) | Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => {
+                    peer.connected.store(false, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+impl Peer {
+    // Sends the new client its id, then listens for its cursor and sends it
+    // frames on threads of their own.
+    fn start(mut stream: TcpStream, id: u8) -> std::io::Result<Peer> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        stream.write_all(&[id])?;
+        let cursor = Arc::new(Mutex::new(None));
+        let connected = Arc::new(AtomicBool::new(true));
+        let mut reader = stream.try_clone()?;
+        let (shared_cursor, still_connected) = (Arc::clone(&cursor), Arc::clone(&connected));
+        thread::spawn(move || {
+            let mut message = [0; CURSOR_MESSAGE];
+            while reader.read_exact(&mut message).is_ok() {
+                *shared_cursor.lock().expect("cursor lock poisoned") =
+                    Some(decode_cursor(&message));
+            }
+            still_connected.store(false, Ordering::Relaxed);
+        });
+        let (frames, queued) = mpsc::sync_channel::<Arc<[u8]>>(QUEUED_FRAMES);
+        let still_connected = Arc::clone(&connected);
+        thread::spawn(move || {
+            // Ends when the host lets go of the peer, or the client stops keeping up.
+            for frame in queued {
+                if let Err(e) = stream.write_all(&frame) {
+                    println!("User {id} left: {e}");
+                    break;
+                }
+            }
+            still_connected.store(false, Ordering::Relaxed);
+            // Also stops the reading thread.
+            let _ = stream.shutdown(Shutdown::Both);
+        });
+        Ok(Peer {
+            id,
+            frames,
+            cursor,
+            connected,
+        })
+    }
+}
+
+impl Client {
+    fn connect(address: &str) -> std::io::Result<Client> {
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut id = [0];
+        stream.read_exact(&mut id)?;
+        println!("Joined {address} as user {}", id[0]);
+
+        let latest = Arc::new(Mutex::new(None));
+        let connected = Arc::new(AtomicBool::new(true));
+        let mut reader = stream.try_clone()?;
+        let (shared_latest, still_connected) = (Arc::clone(&latest), Arc::clone(&connected));
+        thread::spawn(move || {
+            while let Some(snapshot) = read_frame(&mut reader) {
+                *shared_latest.lock().expect("frame lock poisoned") = Some(snapshot);
+            }
+            still_connected.store(false, Ordering::Relaxed);
+        });
+        Ok(Client {
+            stream,
+            address: address.to_string(),
+            id: id[0],
+            latest,
+            connected,
+            cursors: Vec::new(),
+        })
+    }
+
+    fn send_cursor(&mut self, position: Vec2, mode: CursorMode) -> std::io::Result<()> {
+        self.stream.write_all(&encode_cursor(position, mode))
+    }
+
+    // Puts the boids where the host last said they were.
+    fn follow(&mut self, model: &mut Model, env: &Environment) {
+        let Some(snapshot) = self.latest.lock().expect("frame lock poisoned").take() else {
+            return;
+        };
+        let world = Rect::from_w_h(snapshot.world.x, snapshot.world.y);
+        if world != model.world {
+            model.set_world(world);
+        }
+        self.cursors = snapshot
+            .cursors
+            .iter()
+            .filter(|(id, _, _)| *id != self.id)
+            .map(|(_, position, mode)| (*position, *mode))
+            .collect();
+        let sim = model.sim_mut();
+        for (boids, states, boid_type) in [
+            (&mut sim.flock, &snapshot.prey, BoidType::Prey),
+            (&mut sim.predators, &snapshot.predators, BoidType::Predator),
+        ] {
+            boids.truncate(states.len());
+            while boids.len() < states.len() {
+                boids.push(Boid::new(0.0, 0.0, boid_type));
+            }
+            for (boid, (position, velocity)) in boids.iter_mut().zip(states) {
+                boid.position = *position;
+                boid.velocity = *velocity;
+                boid.record_trail(env.trail_length);
+            }
+        }
+        sim.boid_options.flock_size = sim.flock.len();
+        sim.predator_options.flock_size = sim.predators.len();
+        for boid in &mut sim.flock {
+            sim.boid_options.apply(boid);
+        }
+        for predator in &mut sim.predators {
+            sim.predator_options.apply(predator);
+        }
+    }
+}
+
+// The reading thread has its own handle on the connection, so it has to be
+// shut down rather than just dropped.
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// Trades cursors and boids with everyone else in the session, before the
+// simulation steps. Joining users' cursors are added to `env`.
+pub fn update_session(app: &App, model: &mut Model, env: &mut Environment) {
+    let Some(mut session) = model.session.take() else {
+        return;
+    };
+    match &mut session {
+        Session::Host(host) => host.accept(),
+        Session::Client(client) => {
+            let sent = client.send_cursor(model.cursor(app), model.cursor_mode);
+            if sent.is_err() || !client.connected.load(Ordering::Relaxed) {
+                println!(
+                    "Lost the connection to {}, carrying on alone",
+                    client.address
+                );
+                return;
+            }
+            client.follow(model, env);
+        }
+    }
+    env.remote_cursors = session.cursors();
+    model.session = Some(session);
+}
+
+// Sends the host's boids out to everyone who's joined, after the simulation steps.
+pub fn broadcast(model: &mut Model, env: &Environment) {
+    let Some(Session::Host(host)) = &mut model.session else {
+        return;
+    };
+    host.broadcast(&model.sims[model.active], env);
+}
+
+// Marks where everyone else in the session is pointing.
+pub fn draw_remote_cursors(model: &Model, draw: &Draw) {
+    let Some(session) = &model.session else {
+        return;
+    };
+    // Drawn in the world, but kept the same size on screen at any zoom.
+    let scale = 1.0 / model.camera.points_per_unit();
+    for (position, mode) in session.cursors() {
+        draw.ellipse()
+            .xy(position)
+            .radius(8.0 * scale)
+            .no_fill()
+            .stroke_weight(1.5 * scale)
+            .stroke_color(model.theme().cursor);
+        let sign = match mode {
+            CursorMode::Attract => "+",
+            CursorMode::Avoid => "-",
+            CursorMode::Ignore => continue,
+        };
+        draw.text(sign)
+            .xy(position + vec2(0.0, 1.0) * scale)
+            .font_size(14)
+            .color(model.theme().cursor);
+    }
+}
+
+fn encode_cursor(position: Vec2, mode: CursorMode) -> [u8; CURSOR_MESSAGE] {
+    let mut message = [0; CURSOR_MESSAGE];
+    message[0..4].copy_from_slice(&position.x.to_le_bytes());
+    message[4..8].copy_from_slice(&position.y.to_le_bytes());
+    message[8] = match mode {
+        CursorMode::Attract => 0,
+        CursorMode::Avoid => 1,
+        CursorMode::Ignore => 2,
+    };
+    message
+}
+
+fn decode_cursor(message: &[u8]) -> (Vec2, CursorMode) {
+    let x = f32::from_le_bytes([message[0], message[1], message[2], message[3]]);
+    let y = f32::from_le_bytes([message[4], message[5], message[6], message[7]]);
+    let mode = match message[8] {
+        0 => CursorMode::Attract,
+        1 => CursorMode::Avoid,
+        _ => CursorMode::Ignore,
+    };
+    // A cursor that isn't a number would turn every boid it touches into NaN.
+    if x.is_finite() && y.is_finite() {
+        (vec2(x, y), mode)
+    } else {
+        (Vec2::ZERO, CursorMode::Ignore)
+    }
+}
+
+// The first id from `next` on that no peer has, going round from 255 back to
+// 1, since 0 is the host's. None once every id is taken.
+fn free_id(next: u8, taken: &[u8]) -> Option<u8> {
+    let next = next.max(1);
+    (next..=u8::MAX)
+        .chain(1..next)
+        .find(|id| !taken.contains(id))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn encode_frame(sim: &Simulation, world: Rect, cursors: &[(u8, Vec2, CursorMode)]) -> Vec<u8> {
+    let half = world.wh() / 2.0;
+    let quantise = |value: f32| (value.clamp(-1.0, 1.0) * I16_MAX).round() as i16;
+    let prey = &sim.flock[..sim.flock.len().min(u16::MAX as usize)];
+    let predators = &sim.predators[..sim.predators.len().min(u16::MAX as usize)];
+    let cursors = &cursors[..cursors.len().min(u8::MAX as usize)];
+
+    let mut frame = vec![0; 4];
+    frame.extend(world.w().to_le_bytes());
+    frame.extend(world.h().to_le_bytes());
+    frame.push(cursors.len() as u8);
+    for (id, position, mode) in cursors {
+        frame.push(*id);
+        frame.extend(encode_cursor(*position, *mode));
+    }
+    frame.extend((prey.len() as u16).to_le_bytes());
+    frame.extend((predators.len() as u16).to_le_bytes());
+    for boid in prey.iter().chain(predators) {
+        let position = boid.position / half;
+        let velocity = boid.velocity * VELOCITY_SCALE / I16_MAX;
+        for value in [position.x, position.y, velocity.x, velocity.y] {
+            frame.extend(quantise(value).to_le_bytes());
+        }
+    }
+    let length = (frame.len() - 4) as u32;
+    frame[0..4].copy_from_slice(&length.to_le_bytes());
+    frame
+}
+
+// The next frame from the host, or `None` once the connection is gone or the
+// host sends something that isn't a frame.
+fn read_frame(stream: &mut impl Read) -> Option<Snapshot> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length).ok()?;
+    let length = usize::try_from(u32::from_le_bytes(length)).ok()?;
+    if length > MAX_FRAME {
+        return None;
+    }
+    let mut frame = vec![0; length];
+    stream.read_exact(&mut frame).ok()?;
+    decode_frame(&frame)
+}
+
+fn decode_frame(frame: &[u8]) -> Option<Snapshot> {
+    let mut rest = frame;
+    let mut take = |n: usize| {
+        let (taken, remaining) = rest.split_at_checked(n)?;
+        rest = remaining;
+        Some(taken)
+    };
+    let f32_at = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let u16_at = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]);
+    let i16_at = |bytes: &[u8]| i16::from_le_bytes([bytes[0], bytes[1]]);
+
+    let world = take(8)?;
+    let world = vec2(f32_at(&world[0..4]), f32_at(&world[4..8]));
+    if !(world.x > 0.0 && world.y > 0.0) {
+        return None;
+    }
+    let cursor_count = take(1)?[0];
+    let cursors = (0..cursor_count)
+        .map(|_| {
+            let cursor = take(FRAME_CURSOR)?;
+            let (position, mode) = decode_cursor(&cursor[1..]);
+            Some((cursor[0], position, mode))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let counts = take(4)?;
+    let (prey, predators) = (u16_at(&counts[0..2]), u16_at(&counts[2..4]));
+    let half = world / 2.0;
+    let mut boids = (0..prey as usize + predators as usize)
+        .map(|_| {
+            let boid = take(FRAME_BOID)?;
+            let value = |i: usize| i16_at(&boid[i..]) as f32 / I16_MAX;
+            Some((
+                vec2(value(0), value(2)) * half,
+                vec2(value(4), value(6)) * I16_MAX / VELOCITY_SCALE,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let predators = boids.split_off(prey as usize);
+    Some(Snapshot {
+        world,
+        cursors,
+        prey: boids,
+        predators,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nannou::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::model::{registry, BoidOptions};
+
+    fn simulation(world: Rect) -> Simulation {
+        let registry = registry();
+        let mut prey = BoidOptions::prey(&registry);
+        prey.flock_size = 50;
+        let mut predators = BoidOptions::predators(&registry);
+        predators.flock_size = 3;
+        Simulation::new(7, world, prey, predators)
+    }
+
+    fn cursors() -> Vec<(u8, Vec2, CursorMode)> {
+        vec![
+            (0, vec2(-120.5, 40.25), CursorMode::Attract),
+            (3, vec2(310.0, -99.0), CursorMode::Avoid),
+            (200, vec2(0.0, 0.0), CursorMode::Ignore),
+        ]
+    }
+
+    // A frame without its length, as `decode_frame` expects.
+    fn body(frame: &[u8]) -> &[u8] {
+        &frame[4..]
+    }
+
+    #[test]
+    fn round_trips_within_quantisation() {
+        let world = Rect::from_w_h(1600.0, 900.0);
+        let mut sim = simulation(world);
+        for boid in sim.flock.iter_mut().chain(&mut sim.predators) {
+            boid.velocity = vec2(3.7, -2.1);
+        }
+        let frame = encode_frame(&sim, world, &cursors());
+        let snapshot = decode_frame(body(&frame)).unwrap();
+
+        assert_eq!(snapshot.world, world.wh());
+        assert_eq!(snapshot.prey.len(), sim.flock.len());
+        assert_eq!(snapshot.predators.len(), sim.predators.len());
+        // Rounding is at most one step of an i16 across the half-world, and one
+        // 1/`VELOCITY_SCALE`th of a point for velocities.
+        let position_error = world.w().max(world.h()) / 2.0 / I16_MAX;
+        let velocity_error = 1.0 / VELOCITY_SCALE;
+        for (boids, states) in [
+            (&sim.flock, &snapshot.prey),
+            (&sim.predators, &snapshot.predators),
+        ] {
+            for (boid, (position, velocity)) in boids.iter().zip(states) {
+                assert!(boid.position.distance(*position) <= position_error);
+                assert!(boid.velocity.distance(*velocity) <= velocity_error);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_cursor_ids_and_modes() {
+        let world = Rect::from_w_h(800.0, 600.0);
+        let frame = encode_frame(&simulation(world), world, &cursors());
+        let snapshot = decode_frame(body(&frame)).unwrap();
+        assert_eq!(snapshot.cursors, cursors());
+    }
+
+    #[test]
+    fn length_prefix_matches() {
+        let world = Rect::from_w_h(800.0, 600.0);
+        let frame = encode_frame(&simulation(world), world, &cursors());
+        assert!(read_frame(&mut frame.as_slice()).is_some());
+        let length = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+        assert_eq!(length as usize, frame.len() - 4);
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let world = Rect::from_w_h(800.0, 600.0);
+        let frame = encode_frame(&simulation(world), world, &cursors());
+        for end in 0..frame.len() - 4 {
+            assert!(decode_frame(&body(&frame)[..end]).is_none(), "{end} bytes");
+        }
+        assert!(read_frame(&mut &frame[..frame.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn garbage_is_rejected_without_panicking() {
+        // An HTTP server answering instead of a host.
+        let mut http: &[u8] = b"HTTP/1.1 400 Bad Request\r\n\r\n";
+        assert!(read_frame(&mut http).is_none());
+        // A length far beyond any real frame is refused before allocating.
+        let mut huge: &[u8] = &u32::MAX.to_le_bytes();
+        assert!(read_frame(&mut huge).is_none());
+        // A world with no size.
+        let mut empty_world = vec![0; 8];
+        empty_world.extend([0, 0, 0, 0, 0]);
+        assert!(decode_frame(&empty_world).is_none());
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let length = rng.gen_range(0..200);
+            let bytes: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            let _ = decode_frame(&bytes);
+        }
+    }
+
+    #[test]
+    fn ids_skip_those_still_in_use() {
+        assert_eq!(free_id(1, &[]), Some(1));
+        assert_eq!(free_id(3, &[3, 4]), Some(5));
+        // Going round past 255 starts again from 1, never the host's 0.
+        assert_eq!(free_id(255, &[255]), Some(1));
+        assert_eq!(free_id(0, &[]), Some(1));
+        let all_but_7: Vec<u8> = (1..=u8::MAX).filter(|&id| id != 7).collect();
+        assert_eq!(free_id(200, &all_but_7), Some(7));
+        let all: Vec<u8> = (1..=u8::MAX).collect();
+        assert_eq!(free_id(42, &all), None);
+    }
+}
@@ -0,0 +1,427 @@
+use nannou::{
+    prelude::*,
+    rand::{rngs::StdRng, Rng, SeedableRng},
+};
+
+use crate::{
+    boids::{
+        Boid,
+        BoidType::{self, Predator, Prey},
+        Forces,
+    },
+    colour::ColourMode,
+    cursor::CursorMode,
+    flock::Flock,
+    leader::{Leader, LeaderTarget},
+    model::BoidOptions,
+    spatial::SpatialGrid,
+    steering::Context,
+};
+
+// A predator this close to a prey boid catches it. Caught boids carry on as
+// before; catches are only counted.
+const CATCH_DISTANCE: f32 = 10.0;
+// Prey closer together than this count as colliding.
+const COLLISION_DISTANCE: f32 = 5.0;
+
+// A predator reaching a prey boid.
+#[derive(Clone)]
+pub struct Catch {
+    pub position: Vec2,
+    pub predator: usize,
+    pub prey: usize,
+}
+
+// Everything a step needs from outside the simulation itself. These settings
+// are shared when two simulations are compared side by side.
+pub struct Environment {
+    pub bounds: Rect,
+    // In world coordinates.
+    pub cursor: Vec2,
+    pub cursor_mode: CursorMode,
+    // Other people's cursors, when sharing a flock over the network.
+    pub remote_cursors: Vec<(Vec2, CursorMode)>,
+    pub leader_target: LeaderTarget,
+    pub leader_weight: f32,
+    pub follow_queue: bool,
+    pub path: Vec<Vec2>,
+    pub path_width: f32,
+    pub path_weight: f32,
+    pub trail_length: usize,
+}
+
+impl Environment {
+    // Settings for running without a window: no cursor, leaders or path.
+    pub fn headless(bounds: Rect) -> Environment {
+        Environment {
+            bounds,
+            cursor: Vec2::ZERO,
+            cursor_mode: CursorMode::Ignore,
+            remote_cursors: Vec::new(),
+            leader_target: LeaderTarget::Wander,
+            leader_weight: 5.0,
+            follow_queue: false,
+            path: Vec::new(),
+            path_width: 50.0,
+            path_weight: 0.0,
+            trail_length: 0,
+        }
+    }
+}
+
+// One flock and its predators, along with the parameters that shape how they
+// move. All randomness comes from `rng`, so two simulations started from the
+// same seed stay identical until their parameters differ.
+#[derive(Clone)]
+pub struct Simulation {
+    pub flock: Vec<Boid>,
+    pub predators: Vec<Boid>,
+    pub grid: SpatialGrid,
+    pub boid_options: BoidOptions,
+    pub predator_options: BoidOptions,
+    pub max_force: f32,
+    pub rng: StdRng,
+    // Prey caught during the last step.
+    pub catches: Vec<Catch>,
+}
+
+// Where the prey are and which way they're heading. Cheap enough to work out
+// every frame, unlike the clusters in `Metrics`.
+pub struct Shape {
+    // Mean position, or the origin with no prey.
+    pub centroid: Vec2,
+    // Root mean square distance from the centroid along each axis, so it can
+    // be scaled the same way as positions.
+    pub spread: Vec2,
+    // How much the flock heads the same way, from 0 (every which way) to 1.
+    pub polarisation: f32,
+}
+
+// A few whole-flock numbers for comparing runs.
+pub struct Metrics {
+    // How much the flock heads the same way, from 0 (every which way) to 1.
+    pub polarisation: f32,
+    pub mean_speed: f32,
+    pub mean_neighbours: f32,
+    pub clusters: usize,
+}
+
+impl Simulation {
+    pub fn new(
+        seed: u64,
+        bounds: Rect,
+        boid_options: BoidOptions,
+        predator_options: BoidOptions,
+    ) -> Simulation {
+        let mut simulation = Simulation {
+            flock: Vec::new(),
+            predators: Vec::new(),
+            grid: SpatialGrid::new(&[], 0.0),
+            boid_options,
+            predator_options,
+            max_force: 0.2,
+            rng: StdRng::seed_from_u64(seed),
+            catches: Vec::new(),
+        };
+        simulation.restart(seed, bounds);
+        simulation
+    }
+
+    // Respawns both species from `seed`. Prey and predators get their own
+    // streams, so changing one count doesn't move the other species around.
+    pub fn restart(&mut self, seed: u64, bounds: Rect) {
+        let mut prey_rng = StdRng::seed_from_u64(seed);
+        let mut predator_rng = StdRng::seed_from_u64(seed.wrapping_add(1));
+        self.flock = Flock::new_flock(bounds, &self.boid_options, &mut prey_rng);
+        self.predators = Flock::new_flock(bounds, &self.predator_options, &mut predator_rng);
+        self.rng = StdRng::seed_from_u64(seed.wrapping_add(2));
+        self.rebuild_grid();
+    }
+
+    pub fn options(&self, boid_type: BoidType) -> &BoidOptions {
+        match boid_type {
+            Prey => &self.boid_options,
+            Predator => &self.predator_options,
+        }
+    }
+
+    pub fn add_boid(&mut self, bounds: Rect, boid_type: BoidType) {
+        let boid = match boid_type {
+            Prey => self.boid_options.spawn(bounds, &mut self.rng),
+            Predator => self.predator_options.spawn(bounds, &mut self.rng),
+        };
+        match boid_type {
+            Prey => self.flock.push(boid),
+            Predator => self.predators.push(boid),
+        }
+    }
+
+    pub fn remove_boid(&mut self, boid_type: BoidType) {
+        match boid_type {
+            Prey => self.flock.pop(),
+            Predator => self.predators.pop(),
+        };
+    }
+
+    // The prey boid drawn under `position`, if any, for click selection.
+    pub fn boid_at(&self, position: Vec2) -> Option<usize> {
+        self.flock
+            .iter()
+            .enumerate()
+            .map(|(i, boid)| (i, boid.position.distance(position)))
+            .filter(|(i, distance)| *distance < self.flock[*i].height)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    // Brings the boids in line with the current settings, ready for the next step.
+    pub fn apply_settings(&mut self, bounds: Rect, leader_count: usize) {
+        self.adjust_boid_count(bounds);
+        self.assign_leaders(leader_count);
+        for boid in &mut self.flock {
+            self.boid_options.apply(boid);
+            boid.max_force = self.max_force;
+        }
+        for predator in &mut self.predators {
+            self.predator_options.apply(predator);
+            predator.max_force = self.max_force;
+        }
+    }
+
+    // The first `leader_count` prey boids lead, everyone else follows.
+    fn assign_leaders(&mut self, leader_count: usize) {
+        for (i, boid) in self.flock.iter_mut().enumerate() {
+            match (i < leader_count, boid.leader.is_some()) {
+                (true, false) => boid.leader = Some(Leader::new(i, &mut self.rng)),
+                (false, true) => boid.leader = None,
+                _ => {}
+            }
+        }
+    }
+
+    fn adjust_boid_count(&mut self, bounds: Rect) {
+        for boid_type in [Predator, Prey] {
+            let n_mod = self.options(boid_type).n_mod;
+            for _ in 0..n_mod.abs() {
+                match n_mod.signum() {
+                    1 => self.add_boid(bounds, boid_type),
+                    -1 => self.remove_boid(boid_type),
+                    _ => {}
+                }
+            }
+        }
+        self.predator_options.n_mod = 0;
+        self.predator_options.flock_size = self.predators.len();
+        self.boid_options.n_mod = 0;
+        self.boid_options.flock_size = self.flock.len();
+    }
+
+    pub fn rebuild_grid(&mut self) {
+        let cell_size = self
+            .flock
+            .iter()
+            .map(|boid| boid.visual_range)
+            .fold(0.0, f32::max);
+        self.grid = SpatialGrid::new(&self.flock, cell_size);
+    }
+
+    // Advances the simulation by one tick.
+    pub fn step(&mut self, env: &Environment) {
+        self.rebuild_grid();
+        if self.flock.is_empty() {
+            return;
+        };
+        let bounds = env.bounds;
+        self.predator_options.steering.start_step();
+        self.boid_options.steering.start_step();
+
+        for i in 0..self.predators.len() {
+            let mut forces = std::mem::take(&mut self.predators[i].forces);
+            if let Some(action) = self.predators[i].action {
+                self.steer_by_action(Predator, i, action, &mut forces, &bounds);
+                continue;
+            }
+            let close_predators = self.predators[i].close_predators(&self.predators);
+            let (mut nearby_boids, close_boids) = self.predators[i].get_neighbours(
+                &self.flock,
+                &self.grid,
+                self.predator_options.neighbourhood,
+            );
+            nearby_boids.extend(close_boids);
+            let context = Context {
+                boid: &self.predators[i],
+                nearby: &nearby_boids,
+                close: &close_predators,
+                predators: &self.predators,
+                leader_weight: 1.0,
+                env,
+            };
+            self.predator_options.steering.steer(&context, &mut forces);
+            let neighbour_count = nearby_boids.len();
+
+            self.predators[i].neighbour_count = neighbour_count;
+            self.predators[i].acceleration += forces.total();
+            self.predators[i].forces = forces;
+            self.predators[i].update();
+        }
+
+        for i in 0..self.flock.len() {
+            let mut forces = std::mem::take(&mut self.flock[i].forces);
+            if let Some(action) = self.flock[i].action {
+                self.flock[i].wrap(&bounds);
+                self.steer_by_action(Prey, i, action, &mut forces, &bounds);
+                continue;
+            }
+            // Leaders ignore the flock and head for their own target instead.
+            if self.flock[i].leader.is_some() {
+                let wander = self.rng.gen_range(-0.3..0.3);
+                forces.clear();
+                forces.push(
+                    "Leader",
+                    self.flock[i].lead(env.leader_target, &bounds, env.cursor, wander),
+                );
+                forces.push("Bounds", self.flock[i].avoid_bounds(&bounds));
+                self.flock[i].neighbour_count = 0;
+            } else {
+                let (nearby_boids, close_boids) = self.flock[i].get_neighbours(
+                    &self.flock,
+                    &self.grid,
+                    self.boid_options.neighbourhood,
+                );
+                let context = Context {
+                    boid: &self.flock[i],
+                    nearby: &nearby_boids,
+                    close: &close_boids,
+                    predators: &self.predators,
+                    leader_weight: env.leader_weight,
+                    env,
+                };
+                self.boid_options.steering.steer(&context, &mut forces);
+                self.flock[i].neighbour_count = nearby_boids.len() + close_boids.len();
+            }
+
+            self.flock[i].acceleration += forces.total();
+            self.flock[i].forces = forces;
+            self.flock[i].wrap(&bounds);
+            self.flock[i].update();
+        }
+        self.record_catches();
+        for boid in self.flock.iter_mut().chain(&mut self.predators) {
+            boid.record_trail(env.trail_length);
+        }
+    }
+
+    // A controlled boid ignores its species' steering and takes `action`
+    // instead, though it still turns away from the edges of the world.
+    fn steer_by_action(
+        &mut self,
+        boid_type: BoidType,
+        i: usize,
+        action: Vec2,
+        forces: &mut Forces,
+        bounds: &Rect,
+    ) {
+        let boid = match boid_type {
+            Prey => &mut self.flock[i],
+            Predator => &mut self.predators[i],
+        };
+        forces.clear();
+        forces.push("Action", action);
+        forces.push("Bounds", boid.avoid_bounds(bounds));
+        boid.neighbour_count = 0;
+        boid.acceleration += forces.total();
+        boid.forces = std::mem::take(forces);
+        boid.update();
+    }
+
+    fn record_catches(&mut self) {
+        self.catches.clear();
+        for (prey, boid) in self.flock.iter_mut().enumerate() {
+            let predator = self
+                .predators
+                .iter()
+                .position(|predator| predator.position.distance(boid.position) < CATCH_DISTANCE);
+            match predator {
+                Some(predator) if !boid.caught => self.catches.push(Catch {
+                    position: boid.position,
+                    predator,
+                    prey,
+                }),
+                _ => {}
+            }
+            boid.caught = predator.is_some();
+        }
+    }
+
+    // Pairs of prey closer than `COLLISION_DISTANCE`, each counted once. Only
+    // accurate straight after `rebuild_grid`.
+    pub fn collisions(&self) -> usize {
+        self.flock
+            .iter()
+            .enumerate()
+            .map(|(i, boid)| {
+                self.grid
+                    .within(&self.flock, boid.position, COLLISION_DISTANCE, |j| j > i)
+                    .len()
+            })
+            .sum()
+    }
+
+    // Density and clusters need an extra pass over the flock, so only pay for them when shown.
+    pub fn update_colour_data(&mut self, mode: ColourMode) {
+        match mode {
+            ColourMode::Density => {
+                let density = Flock::local_density(&self.flock, &self.grid);
+                for (boid, density) in self.flock.iter_mut().zip(density) {
+                    boid.density = density;
+                }
+            }
+            ColourMode::Cluster => {
+                let clusters = Flock::clusters(&self.flock, &self.grid);
+                for (boid, cluster) in self.flock.iter_mut().zip(clusters) {
+                    boid.cluster = cluster;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn shape(&self) -> Shape {
+        let count = self.flock.len().max(1) as f32;
+        let centroid = self
+            .flock
+            .iter()
+            .fold(Vec2::ZERO, |sum, boid| sum + boid.position)
+            / count;
+        let spread = self.flock.iter().fold(Vec2::ZERO, |sum, boid| {
+            let offset = boid.position - centroid;
+            sum + offset * offset
+        }) / count;
+        let heading: Vec2 = self
+            .flock
+            .iter()
+            .map(|boid| boid.velocity.normalize_or_zero())
+            .fold(Vec2::ZERO, |sum, heading| sum + heading);
+        Shape {
+            centroid,
+            spread: vec2(spread.x.sqrt(), spread.y.sqrt()),
+            polarisation: heading.length() / count,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn metrics(&self) -> Metrics {
+        let count = self.flock.len().max(1) as f32;
+        let mut clusters = Flock::clusters(&self.flock, &self.grid);
+        clusters.sort_unstable();
+        clusters.dedup();
+        Metrics {
+            polarisation: self.shape().polarisation,
+            mean_speed: self.flock.iter().map(|b| b.velocity.length()).sum::<f32>() / count,
+            mean_neighbours: self.flock.iter().map(|b| b.neighbour_count).sum::<usize>() as f32
+                / count,
+            clusters: clusters.len(),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    f32::consts::{FRAC_PI_2, SQRT_2},
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use nannou::prelude::*;
+
+use crate::{
+    model::Model,
+    simulation::{Catch, Simulation},
+};
+
+const SAMPLE_RATE: u32 = 48_000;
+// Recordings are played back at this rate, whatever the app actually managed,
+// so every captured frame gets exactly the same length of sound.
+pub const RECORD_FPS: u32 = 60;
+const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / RECORD_FPS) as usize;
+const HARMONICS: usize = 6;
+// The flock's note runs over two octaves up from here as it gets denser.
+const BASE_PITCH: f32 = 110.0;
+const TREMOLO_RATE: f32 = 7.0;
+// How long the crack of a catch takes to die away, in seconds.
+const CATCH_DECAY: f32 = 0.15;
+const VOLUME: f32 = 0.25;
+
+// What's heard from the flock, each from 0 to 1.
+#[derive(Clone, Copy, Default)]
+struct Features {
+    // How many flockmates each boid has on average: the pitch.
+    density: f32,
+    // How much the flock heads the same way. Ordered flocks sound pure,
+    // scattered ones buzz.
+    polarisation: f32,
+    // How close the nearest predator is to its nearest prey: a wavering
+    // tritone over the top.
+    tension: f32,
+    // Where the flock's centre is, left to right: the stereo position.
+    pan: f32,
+}
+
+impl Features {
+    #[allow(clippy::cast_precision_loss)]
+    fn of(sim: &Simulation, world: Rect) -> Features {
+        let flock = &sim.flock;
+        let count = flock.len().max(1) as f32;
+        let neighbours = flock.iter().map(|b| b.neighbour_count).sum::<usize>() as f32 / count;
+        let shape = sim.shape();
+        let tension = sim
+            .predators
+            .iter()
+            .filter_map(|predator| {
+                let nearest = flock
+                    .iter()
+                    .map(|boid| boid.position.distance(predator.position))
+                    .reduce(f32::min)?;
+                Some(1.0 - nearest / predator.visual_range)
+            })
+            .fold(0.0, f32::max);
+        Features {
+            // Halfway up with ten neighbours, and never quite at the top.
+            density: neighbours / (neighbours + 10.0),
+            polarisation: shape.polarisation.clamp(0.0, 1.0),
+            tension: tension.clamp(0.0, 1.0),
+            pan: if flock.is_empty() {
+                0.5
+            } else {
+                ((shape.centroid.x - world.left()) / world.w()).clamp(0.0, 1.0)
+            },
+        }
+    }
+
+    fn lerp(self, to: Features, t: f32) -> Features {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        Features {
+            density: mix(self.density, to.density),
+            polarisation: mix(self.polarisation, to.polarisation),
+            tension: mix(self.tension, to.tension),
+            pan: mix(self.pan, to.pan),
+        }
+    }
+}
+
+// A small additive synth, played one frame of samples at a time. Every phase
+// carries over between frames, so there are no clicks at the joins.
+#[derive(Default)]
+struct Synth {
+    phase: f32,
+    tension_phase: f32,
+    tremolo_phase: f32,
+    // Where the last frame ended, glided from over the next one.
+    features: Features,
+    catch_level: f32,
+    noise: u32,
+}
+
+impl Synth {
+    #[allow(clippy::cast_precision_loss)]
+    fn render(&mut self, target: Features, catches: usize, out: &mut Vec<[f32; 2]>) {
+        if catches > 0 {
+            self.catch_level = 1.0;
+        }
+        let dt = 1.0 / SAMPLE_RATE as f32;
+        let decay = (-dt / CATCH_DECAY).exp();
+        for i in 0..SAMPLES_PER_FRAME {
+            let features = self
+                .features
+                .lerp(target, i as f32 / SAMPLES_PER_FRAME as f32);
+            let pitch = BASE_PITCH * 4.0_f32.powf(features.density);
+            self.phase = (self.phase + pitch * dt).fract();
+            self.tension_phase = (self.tension_phase + pitch * SQRT_2 * dt).fract();
+            self.tremolo_phase = (self.tremolo_phase + TREMOLO_RATE * dt).fract();
+
+            // Higher harmonics fade in as the flock loses its order.
+            let brightness = 1.0 - features.polarisation;
+            let (mut tone, mut total) = (0.0, 0.0);
+            for h in 1..=HARMONICS {
+                let level = brightness.powi(h as i32 - 1) / h as f32;
+                tone += level * (self.phase * h as f32 * TAU).sin();
+                total += level;
+            }
+            tone /= total;
+            let tremolo = 0.5 + 0.5 * (self.tremolo_phase * TAU).sin();
+            tone += features.tension * tremolo * 0.5 * (self.tension_phase * TAU).sin();
+
+            // xorshift, for the noise burst of a catch.
+            self.noise ^= self.noise << 13;
+            self.noise ^= self.noise >> 17;
+            self.noise ^= self.noise << 5;
+            let noise = self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0;
+            let crack = noise * self.catch_level * 0.5;
+            self.catch_level *= decay;
+
+            // Equal power panning, so the flock is as loud in the middle as at the edges.
+            let angle = features.pan * FRAC_PI_2;
+            out.push([
+                (tone * angle.cos() + crack) * VOLUME,
+                (tone * angle.sin() + crack) * VOLUME,
+            ]);
+        }
+        self.features = target;
+    }
+}
+
+// Captures every frame as a numbered PNG, alongside a soundtrack made from the
+// flock. Each frame adds exactly 1/`RECORD_FPS` of a second of sound, so the
+// two line up when the frames are played back at that rate.
+pub struct Recording {
+    pub dir: PathBuf,
+    pub frames: u32,
+    wav: WavWriter<BufWriter<File>>,
+    synth: Synth,
+    samples: Vec<[f32; 2]>,
+}
+
+impl Recording {
+    // Starts recording into the first of `recording-1`, `recording-2`... that
+    // doesn't exist yet.
+    pub fn start() -> Result<Recording, String> {
+        let dir = (1..)
+            .map(|i| PathBuf::from(format!("recording-{i}")))
+            .find(|dir| !dir.exists())
+            .expect("there's always a free name");
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let wav = WavWriter::create(dir.join("sound.wav"), spec).map_err(|e| e.to_string())?;
+        Ok(Recording {
+            dir,
+            frames: 0,
+            wav,
+            synth: Synth {
+                noise: 0x9E37_79B9,
+                ..Synth::default()
+            },
+            samples: Vec::with_capacity(SAMPLES_PER_FRAME),
+        })
+    }
+
+    // Captures the frame about to be drawn, and the sound to go with it. Fails
+    // if the sound couldn't be written, since the frames would no longer line
+    // up with it.
+    pub fn record(&mut self, app: &App, model: &Model, catches: &[Catch]) -> Result<(), String> {
+        let features = Features::of(model.sim(), model.world);
+        self.samples.clear();
+        self.synth
+            .render(features, catches.len(), &mut self.samples);
+        for sample in self.samples.iter().flatten() {
+            #[allow(clippy::cast_possible_truncation)]
+            let sample = (sample.clamp(-1.0, 1.0) * 32767.0) as i16;
+            self.wav
+                .write_sample(sample)
+                .map_err(|e| format!("Couldn't write to {}: {e}", self.dir.display()))?;
+        }
+        // Only once its sound is safely written.
+        if let Some(window) = app.window(model.main_window) {
+            window.capture_frame(self.dir.join(format!("frame-{:05}.png", self.frames)));
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) {
+        let dir = self.dir;
+        match self.wav.finalize() {
+            Ok(()) => println!(
+                "Saved {} frames to {}. To make a video:\n  ffmpeg -framerate {RECORD_FPS} -i {}/frame-%05d.png -i {}/sound.wav -pix_fmt yuv420p -c:a aac recording.mp4",
+                self.frames,
+                dir.display(),
+                dir.display(),
+                dir.display()
+            ),
+            Err(e) => eprintln!("Couldn't finish {}: {e}", dir.display()),
+        }
+    }
+}
+
+pub fn toggle_recording(model: &mut Model) {
+    match model.recording.take() {
+        Some(recording) => recording.finish(),
+        None => match Recording::start() {
+            Ok(recording) => {
+                println!("Recording to {}", recording.dir.display());
+                model.recording = Some(recording);
+            }
+            Err(e) => eprintln!("Couldn't start recording: {e}"),
+        },
+    }
+}
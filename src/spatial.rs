@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use nannou::prelude::*;
+
+use crate::boids::Boid;
+
+// Uniform grid over boid positions, rebuilt once per update. Stores indices into
+// the slice it was built from, so it must be queried with that same slice.
+#[derive(Clone)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    min_cell: (i32, i32),
+    max_cell: (i32, i32),
+}
+
+impl SpatialGrid {
+    pub fn new(flock: &[Boid], cell_size: f32) -> SpatialGrid {
+        let mut grid = SpatialGrid {
+            cell_size: cell_size.max(1.0),
+            cells: HashMap::new(),
+            min_cell: (i32::MAX, i32::MAX),
+            max_cell: (i32::MIN, i32::MIN),
+        };
+        for (i, boid) in flock.iter().enumerate() {
+            let cell = grid.cell(boid.position);
+            grid.min_cell = (grid.min_cell.0.min(cell.0), grid.min_cell.1.min(cell.1));
+            grid.max_cell = (grid.max_cell.0.max(cell.0), grid.max_cell.1.max(cell.1));
+            grid.cells.entry(cell).or_default().push(i);
+        }
+        grid
+    }
+
+    // Outlines of the occupied cells, for the debug overlay.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cells(&self) -> impl Iterator<Item = Rect> + '_ {
+        self.cells.keys().map(|&(x, y)| {
+            Rect::from_x_y_w_h(
+                (x as f32 + 0.5) * self.cell_size,
+                (y as f32 + 0.5) * self.cell_size,
+                self.cell_size,
+                self.cell_size,
+            )
+        })
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn cell(&self, position: Vec2) -> (i32, i32) {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    // Visits every cell at Chebyshev distance `ring` from `centre`.
+    fn ring(&self, centre: (i32, i32), ring: i32, mut visit: impl FnMut(usize)) {
+        for x in centre.0 - ring..=centre.0 + ring {
+            for y in centre.1 - ring..=centre.1 + ring {
+                if (x - centre.0).abs() != ring && (y - centre.1).abs() != ring {
+                    continue;
+                }
+                if let Some(indices) = self.cells.get(&(x, y)) {
+                    indices.iter().for_each(|&i| visit(i));
+                }
+            }
+        }
+    }
+
+    // Furthest ring that can still contain a boid, so searches terminate on
+    // sparse flocks.
+    fn max_ring(&self, centre: (i32, i32)) -> i32 {
+        if self.cells.is_empty() {
+            return -1;
+        }
+        [
+            centre.0 - self.min_cell.0,
+            self.max_cell.0 - centre.0,
+            centre.1 - self.min_cell.1,
+            self.max_cell.1 - centre.1,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0)
+        .max(0)
+    }
+
+    pub fn within(
+        &self,
+        flock: &[Boid],
+        position: Vec2,
+        radius: f32,
+        keep: impl Fn(usize) -> bool,
+    ) -> Vec<usize> {
+        let centre = self.cell(position);
+        #[allow(clippy::cast_possible_truncation)]
+        let rings = ((radius / self.cell_size).floor() as i32 + 1).min(self.max_ring(centre));
+        let mut found = Vec::new();
+        for ring in 0..=rings {
+            self.ring(centre, ring, |i| {
+                if keep(i) && flock[i].position.distance(position) <= radius {
+                    found.push(i);
+                }
+            });
+        }
+        found
+    }
+
+    // The `k` nearest boids to `position`, closest first, optionally limited to
+    // `max_radius`. Expands ring by ring until nothing unvisited can be closer
+    // than the current k-th candidate.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn nearest(
+        &self,
+        flock: &[Boid],
+        position: Vec2,
+        k: usize,
+        max_radius: Option<f32>,
+        keep: impl Fn(usize) -> bool,
+    ) -> Vec<usize> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let radius = max_radius.unwrap_or(f32::INFINITY);
+        let centre = self.cell(position);
+        let mut candidates: Vec<(f32, usize)> = Vec::new();
+        for ring in 0..=self.max_ring(centre) {
+            let ring_start = (ring - 1).max(0) as f32 * self.cell_size;
+            if ring_start > radius {
+                break;
+            }
+            self.ring(centre, ring, |i| {
+                let d = flock[i].position.distance(position);
+                if keep(i) && d <= radius {
+                    candidates.push((d, i));
+                }
+            });
+            if candidates.len() >= k {
+                candidates.select_nth_unstable_by(k - 1, |a, b| a.0.total_cmp(&b.0));
+                candidates.truncate(k);
+                let kth = candidates.iter().map(|c| c.0).fold(0.0, f32::max);
+                // Anything outside this ring is at least `ring` cells away.
+                if kth <= ring as f32 * self.cell_size {
+                    break;
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+        candidates.into_iter().map(|(_, i)| i).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nannou::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::boids::BoidType;
+
+    fn flock(seed: u64, count: usize) -> Vec<Boid> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| {
+                let x = rng.gen_range(-400.0..400.0);
+                let y = rng.gen_range(-300.0..300.0);
+                Boid::new(x, y, BoidType::Prey)
+            })
+            .collect()
+    }
+
+    // Distances of the boids found, sorted, so ties between equally distant
+    // boids can't make otherwise correct answers differ.
+    fn distances(flock: &[Boid], position: Vec2, found: &[usize]) -> Vec<f32> {
+        let mut distances: Vec<f32> = found
+            .iter()
+            .map(|&i| flock[i].position.distance(position))
+            .collect();
+        distances.sort_by(f32::total_cmp);
+        distances
+    }
+
+    fn brute_force(
+        flock: &[Boid],
+        position: Vec2,
+        k: usize,
+        radius: f32,
+        skip: usize,
+    ) -> Vec<usize> {
+        let mut all: Vec<usize> = (0..flock.len())
+            .filter(|&i| i != skip && flock[i].position.distance(position) <= radius)
+            .collect();
+        all.sort_by(|&a, &b| {
+            let a = flock[a].position.distance(position);
+            let b = flock[b].position.distance(position);
+            a.total_cmp(&b)
+        });
+        all.truncate(k);
+        all
+    }
+
+    // Queries from each boid, and from points inside and well outside the flock.
+    fn queries(flock: &[Boid]) -> Vec<(Vec2, usize)> {
+        let mut queries: Vec<(Vec2, usize)> = flock
+            .iter()
+            .enumerate()
+            .map(|(i, boid)| (boid.position, i))
+            .collect();
+        for position in [vec2(0.0, 0.0), vec2(3000.0, -2000.0), vec2(-5000.0, 0.0)] {
+            queries.push((position, usize::MAX));
+        }
+        queries
+    }
+
+    #[test]
+    fn metric_matches_brute_force() {
+        for (seed, count, cell_size) in [(1, 200, 80.0), (2, 50, 30.0), (3, 500, 200.0)] {
+            let flock = flock(seed, count);
+            let grid = SpatialGrid::new(&flock, cell_size);
+            for radius in [10.0, 80.0, 250.0] {
+                for (position, skip) in queries(&flock) {
+                    let mut found = grid.within(&flock, position, radius, |i| i != skip);
+                    let mut expected = brute_force(&flock, position, usize::MAX, radius, skip);
+                    found.sort_unstable();
+                    expected.sort_unstable();
+                    assert_eq!(found, expected, "seed {seed}, radius {radius}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn topological_matches_brute_force() {
+        for (seed, count, cell_size) in [(4, 200, 80.0), (5, 50, 30.0), (6, 10, 100.0)] {
+            let flock = flock(seed, count);
+            let grid = SpatialGrid::new(&flock, cell_size);
+            // Including more neighbours than there are boids.
+            for k in [1, 7, 20, count, count + 5] {
+                for (position, skip) in queries(&flock) {
+                    let found = grid.nearest(&flock, position, k, None, |i| i != skip);
+                    let expected = brute_force(&flock, position, k, f32::INFINITY, skip);
+                    assert_eq!(
+                        distances(&flock, position, &found),
+                        distances(&flock, position, &expected),
+                        "seed {seed}, k {k}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hybrid_matches_brute_force() {
+        for (seed, count, cell_size) in [(7, 200, 80.0), (8, 50, 30.0)] {
+            let flock = flock(seed, count);
+            let grid = SpatialGrid::new(&flock, cell_size);
+            for (k, radius) in [(7, 80.0), (3, 20.0), (count + 1, 150.0)] {
+                for (position, skip) in queries(&flock) {
+                    let found = grid.nearest(&flock, position, k, Some(radius), |i| i != skip);
+                    let expected = brute_force(&flock, position, k, radius, skip);
+                    assert_eq!(
+                        distances(&flock, position, &found),
+                        distances(&flock, position, &expected),
+                        "seed {seed}, k {k}, radius {radius}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_is_closest_first() {
+        let flock = flock(9, 100);
+        let grid = SpatialGrid::new(&flock, 50.0);
+        let found = grid.nearest(&flock, Vec2::ZERO, 20, None, |_| true);
+        let in_order = distances(&flock, Vec2::ZERO, &found);
+        let as_found: Vec<f32> = found
+            .iter()
+            .map(|&i| flock[i].position.distance(Vec2::ZERO))
+            .collect();
+        assert_eq!(as_found, in_order);
+    }
+
+    #[test]
+    fn empty_grid_finds_nothing() {
+        let grid = SpatialGrid::new(&[], 80.0);
+        assert!(grid.within(&[], Vec2::ZERO, 100.0, |_| true).is_empty());
+        assert!(grid.nearest(&[], Vec2::ZERO, 7, None, |_| true).is_empty());
+        assert!(grid
+            .nearest(&[], Vec2::ZERO, 7, Some(100.0), |_| true)
+            .is_empty());
+    }
+}
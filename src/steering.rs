@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use nannou::prelude::*;
+
+use crate::{
+    boids::{align, Boid, BoidType, Forces},
+    simulation::Environment,
+};
+
+// Everything a behaviour gets to look at when steering one boid.
+pub struct Context<'a> {
+    pub boid: &'a Boid,
+    // Who the boid is steering with: flockmates for prey, prey for predators.
+    pub nearby: &'a [&'a Boid],
+    // Who the boid is too close to: flockmates for prey, other predators for predators.
+    pub close: &'a [&'a Boid],
+    pub predators: &'a [Boid],
+    // How much leaders count for in `nearby`.
+    pub leader_weight: f32,
+    pub env: &'a Environment,
+}
+
+// One steering rule. Each species runs through its own weighted list of these
+// and adds up what they return.
+pub trait SteeringBehavior: Send + Sync {
+    // Shown on screen, and used to find the behaviour in a `Registry`.
+    fn name(&self) -> &'static str;
+
+    fn steer(&self, context: &Context) -> Vec2;
+
+    // Called once a frame before any steering, for behaviours that keep
+    // themselves up to date, like scripts reloading on save.
+    fn refresh(&self) {}
+
+    // Called at the start of every step, before any boid steers, for
+    // behaviours that budget their time per step, like scripts.
+    fn start_step(&self) {}
+}
+
+struct Alignment;
+struct Separation;
+struct Cohesion;
+struct PredatorAvoidance;
+struct Cursor;
+struct Bounds;
+struct FollowLeader;
+struct FollowPath;
+
+impl SteeringBehavior for Alignment {
+    fn name(&self) -> &'static str {
+        "Alignment"
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        align(context.nearby, context.leader_weight)
+    }
+}
+
+impl SteeringBehavior for Separation {
+    fn name(&self) -> &'static str {
+        "Separation"
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        context.boid.separate(context.close)
+    }
+}
+
+impl SteeringBehavior for Cohesion {
+    fn name(&self) -> &'static str {
+        "Cohesion"
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        context.boid.cohere(context.nearby, context.leader_weight)
+    }
+}
+
+impl SteeringBehavior for PredatorAvoidance {
+    fn name(&self) -> &'static str {
+        "Predators"
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        context.boid.avoid_predators(context.predators)
+    }
+}
+
+impl SteeringBehavior for Cursor {
+    fn name(&self) -> &'static str {
+        "Cursor"
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        let env = context.env;
+        std::iter::once((env.cursor, env.cursor_mode))
+            .chain(env.remote_cursors.iter().copied())
+            .map(|(cursor, mode)| context.boid.cursor_interaction(cursor, &mode))
+            .fold(Vec2::ZERO, |sum, force| sum + force)
+    }
+}
+
+impl SteeringBehavior for Bounds {
+    fn name(&self) -> &'static str {
+        "Bounds"
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        context.boid.avoid_bounds(&context.env.bounds)
+    }
+}
+
+// Queueing up behind a leader, when the follow queue is switched on.
+impl SteeringBehavior for FollowLeader {
+    fn name(&self) -> &'static str {
+        "Leader"
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        if context.env.follow_queue {
+            context.boid.follow_leader(context.nearby)
+        } else {
+            Vec2::ZERO
+        }
+    }
+}
+
+// Also scaled by the path weight, which every simulation shares.
+impl SteeringBehavior for FollowPath {
+    fn name(&self) -> &'static str {
+        "Path"
+    }
+
+    fn steer(&self, context: &Context) -> Vec2 {
+        let env = context.env;
+        context.boid.follow_path(&env.path, env.path_width) * env.path_weight
+    }
+}
+
+// Every behaviour a species can be given, in the order they're listed.
+pub struct Registry {
+    behaviours: Vec<Arc<dyn SteeringBehavior>>,
+}
+
+impl Registry {
+    pub fn built_in() -> Registry {
+        let mut registry = Registry {
+            behaviours: Vec::new(),
+        };
+        registry.register(Alignment);
+        registry.register(Separation);
+        registry.register(Cohesion);
+        registry.register(PredatorAvoidance);
+        registry.register(Cursor);
+        registry.register(Bounds);
+        registry.register(FollowLeader);
+        registry.register(FollowPath);
+        registry
+    }
+
+    // Adds a behaviour, replacing any already registered under the same name.
+    pub fn register(&mut self, behaviour: impl SteeringBehavior + 'static) {
+        let behaviour: Arc<dyn SteeringBehavior> = Arc::new(behaviour);
+        match self.position(behaviour.name()) {
+            Some(i) => self.behaviours[i] = behaviour,
+            None => self.behaviours.push(behaviour),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn SteeringBehavior>> {
+        self.position(name).map(|i| Arc::clone(&self.behaviours[i]))
+    }
+
+    pub fn refresh(&self) {
+        for behaviour in &self.behaviours {
+            behaviour.refresh();
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.behaviours.iter().map(|b| b.name()).collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.behaviours.iter().position(|b| b.name() == name)
+    }
+
+    // The list a species starts with. Everything registered is on it, but only
+    // the behaviours the species has always used are switched on.
+    pub fn steering_for(&self, boid_type: BoidType) -> Steering {
+        let defaults: &[(&str, f32)] = match boid_type {
+            BoidType::Prey => &[
+                ("Alignment", 0.25),
+                ("Separation", 0.75),
+                ("Cohesion", 0.25),
+                ("Predators", 1.0),
+                ("Cursor", 1.0),
+                ("Bounds", 1.0),
+                ("Leader", 1.0),
+                ("Path", 1.0),
+            ],
+            BoidType::Predator => &[("Separation", 1.0), ("Cohesion", 1.0), ("Bounds", 1.0)],
+        };
+        let mut steering = Steering {
+            behaviours: defaults
+                .iter()
+                .filter_map(|(name, weight)| {
+                    Some(Weighted {
+                        behaviour: self.get(name)?,
+                        weight: *weight,
+                        enabled: true,
+                    })
+                })
+                .collect(),
+        };
+        for behaviour in &self.behaviours {
+            if steering.find(behaviour.name()).is_none() {
+                steering.behaviours.push(Weighted {
+                    behaviour: Arc::clone(behaviour),
+                    weight: 1.0,
+                    enabled: false,
+                });
+            }
+        }
+        steering
+    }
+}
+
+#[derive(Clone)]
+pub struct Weighted {
+    pub behaviour: Arc<dyn SteeringBehavior>,
+    pub weight: f32,
+    pub enabled: bool,
+}
+
+// A species' behaviours, in the order they're worked out and shown.
+#[derive(Clone)]
+pub struct Steering {
+    pub behaviours: Vec<Weighted>,
+}
+
+impl Steering {
+    fn find(&self, name: &str) -> Option<&Weighted> {
+        self.behaviours.iter().find(|b| b.behaviour.name() == name)
+    }
+
+    // The weight of behaviour `name`, or 0.0 if it's missing or switched off.
+    pub fn weight(&self, name: &str) -> f32 {
+        self.find(name)
+            .filter(|b| b.enabled)
+            .map_or(0.0, |b| b.weight)
+    }
+
+    pub fn weight_mut(&mut self, name: &str) -> Option<&mut f32> {
+        self.behaviours
+            .iter_mut()
+            .find(|b| b.behaviour.name() == name)
+            .map(|b| &mut b.weight)
+    }
+
+    pub fn start_step(&self) {
+        for weighted in self.behaviours.iter().filter(|b| b.enabled) {
+            weighted.behaviour.start_step();
+        }
+    }
+
+    // Runs every enabled behaviour, recording each weighted force in `forces`.
+    pub fn steer(&self, context: &Context, forces: &mut Forces) {
+        forces.clear();
+        for weighted in self.behaviours.iter().filter(|b| b.enabled) {
+            let force = weighted.behaviour.steer(context) * weighted.weight;
+            forces.push(weighted.behaviour.name(), force);
+        }
+    }
+}
@@ -1,16 +1,170 @@
-#[derive(PartialEq)]
-pub enum Theme {
-    Normal,
-    Grey,
-    DeepSea,
+use std::path::Path;
+
+use nannou::prelude::*;
+use serde::Deserialize;
+
+// Every colour the app draws with. Built-in themes are below; more can be
+// dropped into `themes/` as TOML files.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Theme {
+    pub name: String,
+    pub background: Rgb8,
+    pub prey: Rgb8,
+    pub predator: Rgb8,
+    pub leader: Rgb8,
+    pub text: Rgb8,
+    pub cursor: Rgb8,
+    pub overlay: Rgb8,
 }
 
 impl Theme {
-    pub fn next(&self) -> Theme {
-        match self {
-            Theme::Normal => Theme::Grey,
-            Theme::Grey => Theme::DeepSea,
-            Theme::DeepSea => Theme::Normal,
+    pub fn normal() -> Theme {
+        Theme {
+            name: "Normal".to_string(),
+            background: SKYBLUE,
+            prey: BLACK,
+            predator: DARKRED,
+            leader: ORANGE,
+            text: BLACK,
+            cursor: BLACK,
+            overlay: BLACK,
+        }
+    }
+
+    pub fn grey() -> Theme {
+        Theme {
+            name: "Grey".to_string(),
+            background: GREY,
+            ..Theme::normal()
+        }
+    }
+
+    pub fn deep_sea() -> Theme {
+        Theme {
+            name: "DeepSea".to_string(),
+            background: BLACK,
+            prey: rgb8(200, 200, 200),
+            predator: rgb8(220, 90, 90),
+            leader: ORANGE,
+            text: rgb8(200, 200, 200),
+            cursor: WHITE,
+            overlay: WHITE,
+        }
+    }
+
+    // Okabe-Ito palette, distinguishable with the common forms of colour blindness.
+    pub fn colour_blind() -> Theme {
+        Theme {
+            name: "ColourBlind".to_string(),
+            background: rgb8(245, 245, 245),
+            prey: rgb8(0, 114, 178),
+            predator: rgb8(213, 94, 0),
+            leader: rgb8(230, 159, 0),
+            text: BLACK,
+            cursor: BLACK,
+            overlay: rgb8(0, 158, 115),
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        Theme {
+            name: "HighContrast".to_string(),
+            background: BLACK,
+            prey: WHITE,
+            predator: YELLOW,
+            leader: CYAN,
+            text: WHITE,
+            cursor: WHITE,
+            overlay: LIME,
         }
     }
+
+    pub fn built_in() -> Vec<Theme> {
+        vec![
+            Theme::normal(),
+            Theme::grey(),
+            Theme::deep_sea(),
+            Theme::colour_blind(),
+            Theme::high_contrast(),
+        ]
+    }
+
+    // Built-ins followed by any user themes in `dir`. Files that don't parse are
+    // reported and skipped rather than stopping the app from starting.
+    pub fn load_all(dir: impl AsRef<Path>) -> Vec<Theme> {
+        let mut themes = Theme::built_in();
+        let Ok(entries) = std::fs::read_dir(dir.as_ref()) else {
+            return themes;
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            match Theme::load(&path) {
+                Ok(theme) => themes.push(theme),
+                Err(e) => eprintln!("Skipping theme {}: {e}", path.display()),
+            }
+        }
+        themes
+    }
+
+    pub fn load(path: &Path) -> Result<Theme, String> {
+        let file: ThemeFile = load_from_toml(path).map_err(|e| e.to_string())?;
+        let base = Theme::normal();
+        let colour = |value: Option<String>, fallback: Rgb8| match value {
+            Some(hex) => parse_hex(&hex),
+            None => Ok(fallback),
+        };
+        Ok(Theme {
+            name: file.name.unwrap_or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }),
+            background: colour(file.background, base.background)?,
+            prey: colour(file.prey, base.prey)?,
+            predator: colour(file.predator, base.predator)?,
+            leader: colour(file.leader, base.leader)?,
+            text: colour(file.text, base.text)?,
+            cursor: colour(file.cursor, base.cursor)?,
+            overlay: colour(file.overlay, base.overlay)?,
+        })
+    }
+}
+
+// On-disk form of a theme. Colours are "#rrggbb" strings, and anything left out
+// falls back to the Normal theme.
+#[derive(Deserialize)]
+struct ThemeFile {
+    name: Option<String>,
+    background: Option<String>,
+    prey: Option<String>,
+    predator: Option<String>,
+    leader: Option<String>,
+    text: Option<String>,
+    cursor: Option<String>,
+    overlay: Option<String>,
+}
+
+fn parse_hex(hex: &str) -> Result<Rgb8, String> {
+    let digits = hex.trim().trim_start_matches('#');
+    let channel = |i: usize| {
+        digits
+            .get(i..i + 2)
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            .ok_or_else(|| format!("invalid colour {hex:?}, expected \"#rrggbb\""))
+    };
+    if digits.len() != 6 {
+        return Err(format!("invalid colour {hex:?}, expected \"#rrggbb\""));
+    }
+    Ok(rgb8(channel(0)?, channel(2)?, channel(4)?))
+}
+
+// A theme colour at partial opacity, for overlays and trails.
+pub fn faded(colour: Rgb8, alpha: f32) -> Rgba {
+    let colour: Rgb = colour.into_format();
+    rgba(colour.red, colour.green, colour.blue, alpha)
 }
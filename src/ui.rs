@@ -1,49 +1,399 @@
 use nannou::prelude::*;
 
-use crate::model::Model;
+use crate::{
+    bindings::Action,
+    camera::ZOOM_RANGE,
+    clock::TIME_SCALE_RANGE,
+    colour::ColourMode,
+    gui::Layout,
+    keymaps::perform,
+    model::{
+        BoidOptions, Model, MAX_FORCE_RANGE, MAX_PREDATORS, MAX_PREY, MODIFIER_RANGE,
+        NEAREST_K_RANGE, PROTECTION_RANGE, SPEED_RANGE, START_SPEED_RANGE, VISION_RANGE,
+        WORLD_SIZE_RANGE,
+    },
+    neighbourhood::Neighbourhood,
+    preset::Preset,
+    theme::faded,
+};
 
 pub fn draw_information_text(app: &App, model: &Model, draw: &Draw) {
-    draw_settings(app, model, draw);
+    let viewports = model.viewports(app.window_rect());
+    for (i, viewport) in viewports.into_iter().enumerate() {
+        draw_settings(app, model, i, viewport, draw);
+    }
     draw_instructions(app, model, draw);
 }
 
-pub fn draw_settings(app: &App, model: &Model, draw: &Draw) {
-    let position = app
-        .window_rect()
-        .pad_bottom(50.0)
-        .pad_left(100.0)
-        .bottom_left();
+// The settings and metrics for simulation `index`, in the corner of its half of
+// the window. Settings every simulation shares are only shown once, on the left.
+pub fn draw_settings(app: &App, model: &Model, index: usize, viewport: Rect, draw: &Draw) {
+    let position = viewport.pad_bottom(50.0).pad_left(100.0).bottom_left();
     if model.show_text {
-        draw.text(
-            format!(
-                "Boids: {}\nAlignment: {:.1}%\nCohesion: {:.1}%\nSeparation: {:.1}%\nCursor Mode: {:#?}\nFPS {:.0}",
-                model.boid_options.flock_size,
-                // Added 0.001 so -0.0 wouldn't show up when rounding
-                (model.alignment_modifier + 0.0001) * 100.0,
-                (model.cohesion_modifier + 0.0001) * 100.0,
-                (model.separation_modifier + 0.0001) * 100.0,
-                model.cursor_mode,
-                app.fps()
-
-            )
-            .trim(),
-        )
-        .color(BLACK)
-        .left_justify()
-        .xy(position);
+        let sim = &model.sims[index];
+        let metrics = sim.metrics();
+        let mut text = String::new();
+        if model.comparing() {
+            let side = if index == 0 { "Left" } else { "Right" };
+            let active = if index == model.active {
+                " (editing)"
+            } else {
+                ""
+            };
+            text += &format!("{side}{active}\n");
+        }
+        text += &format!(
+            "Boids: {}\nAlignment: {:.1}%\nCohesion: {:.1}%\nSeparation: {:.1}%\nNeighbours: {:?}\nOrder: {:.2}\nMean speed: {:.2}\nMean neighbours: {:.1}\nClusters: {}",
+            sim.boid_options.flock_size,
+            // Added 0.001 so -0.0 wouldn't show up when rounding
+            (sim.boid_options.steering.weight("Alignment") + 0.0001) * 100.0,
+            (sim.boid_options.steering.weight("Cohesion") + 0.0001) * 100.0,
+            (sim.boid_options.steering.weight("Separation") + 0.0001) * 100.0,
+            sim.boid_options.neighbourhood,
+            metrics.polarisation,
+            metrics.mean_speed,
+            metrics.mean_neighbours,
+            metrics.clusters,
+        );
+        if index > 0 {
+            draw.text(&text)
+                .color(model.theme().text)
+                .left_justify()
+                .xy(position);
+            return;
+        }
+        text += &format!(
+            "\nCursor Mode: {:#?}\nTheme: {}\nLeaders: {} ({:?}{})\nPath: {:?}\nTime: {}{:.2}x\nSeed: {}\nFPS {:.0}",
+            model.cursor_mode,
+            model.theme().name,
+            model.leader_count,
+            model.leader_target,
+            if model.follow_queue { ", queueing" } else { "" },
+            model.path_mode,
+            if model.clock.paused { "paused at " } else { "" },
+            model.clock.time_scale,
+            model.seed,
+            app.fps()
+        );
+        if let Some(code) = &model.preset_code {
+            text += &format!("\nPreset code: {code}");
+        }
+        if let Some(session) = &model.session {
+            text += &format!("\n{}", session.status());
+        }
+        if let Some(osc) = &model.osc {
+            text += &format!("\n{}", osc.status());
+        }
+        #[cfg(feature = "websocket")]
+        if let Some(websocket) = &model.websocket {
+            text += &format!("\n{}", websocket.status());
+        }
+        if let Some(recording) = &model.recording {
+            text += &format!(
+                "\nRecording to {}: {} frames",
+                recording.dir.display(),
+                recording.frames
+            );
+        }
+        draw.text(text.trim())
+            .color(model.theme().text)
+            .left_justify()
+            .xy(position);
     }
 }
 
 pub fn draw_instructions(app: &App, model: &Model, draw: &Draw) {
     let position = app.window_rect().pad_top(50.0).pad_left(100.0).top_left();
     if model.show_text {
-        draw.text(
-            " Up/Down   Add/Remove Boids\nu/i   Alignment\nj/k   Cohesion\nm/,  Separation\nc     Cursor Mode\nh     Hide Text\nq   Quit"
-                .to_string()
-                .trim(),
+        let mut lines: Vec<String> = model
+            .bindings
+            .instructions()
+            .into_iter()
+            .map(|(keys, label)| format!("{keys}   {label}"))
+            .collect();
+        lines.push("Right Click  Add Path Point".to_string());
+        lines.push("Click  Inspect Boid".to_string());
+        draw.text(&lines.join("\n"))
+            .left_justify()
+            .color(model.theme().text)
+            .xy(position);
+    }
+}
+
+pub fn draw_path(model: &Model, draw: &Draw) {
+    let points = model.path.polyline(model.path_mode);
+    if points.len() < 2 {
+        return;
+    }
+    draw.polyline()
+        .weight(model.path.width)
+        .join_round()
+        .points(points.clone())
+        .color(faded(model.theme().overlay, 0.1));
+    draw.polyline()
+        .weight(1.0)
+        .points(points)
+        .color(faded(model.theme().overlay, 0.3));
+    for point in &model.path.points {
+        draw.ellipse()
+            .xy(*point)
+            .radius(3.0)
+            .color(faded(model.theme().overlay, 0.5));
+    }
+}
+
+// The edge of the world, which isn't necessarily the edge of the window.
+pub fn draw_world_bounds(model: &Model, draw: &Draw) {
+    draw.rect()
+        .xy(model.world.xy())
+        .wh(model.world.wh())
+        .no_fill()
+        .stroke_weight(1.0)
+        .stroke(faded(model.theme().overlay, 0.3));
+}
+
+// A highlight around the half being changed, so it's clear which one that is.
+pub fn draw_active_side(app: &App, model: &Model, draw: &Draw) {
+    if !model.comparing() {
+        return;
+    }
+    let viewports = model.viewports(app.window_rect());
+    let divider = viewports[0].right();
+    draw.line()
+        .start(vec2(divider, viewports[0].top()))
+        .end(vec2(divider, viewports[0].bottom()))
+        .weight(2.0)
+        .color(faded(model.theme().overlay, 0.5));
+    let active = viewports[model.active].pad(1.0);
+    draw.rect()
+        .xy(active.xy())
+        .wh(active.wh())
+        .no_fill()
+        .stroke_weight(2.0)
+        .stroke(faded(model.theme().cursor, 0.6));
+}
+
+// Details of the selected boid, which always belongs to the active simulation.
+// `world` draws into that simulation's half.
+pub fn draw_inspector(app: &App, model: &Model, draw: &Draw, world: &Draw) {
+    let sim = model.sim();
+    let Some(boid) = model.selected.and_then(|i| sim.flock.get(i)) else {
+        return;
+    };
+
+    world
+        .ellipse()
+        .xy(boid.position)
+        .radius(boid.visual_range)
+        .no_fill()
+        .stroke_weight(1.0)
+        .stroke(faded(model.theme().overlay, 0.6));
+    world
+        .ellipse()
+        .xy(boid.position)
+        .radius(boid.protected_range)
+        .no_fill()
+        .stroke_weight(1.0)
+        .stroke(faded(model.theme().predator, 0.6));
+    let (nearby_boids, close_boids) =
+        boid.get_neighbours(&sim.flock, &sim.grid, sim.boid_options.neighbourhood);
+    for neighbour in nearby_boids.iter().chain(&close_boids) {
+        world
+            .line()
+            .start(boid.position)
+            .end(neighbour.position)
+            .weight(1.0)
+            .color(faded(model.theme().overlay, 0.4));
+    }
+
+    let forces = boid
+        .forces
+        .named()
+        .iter()
+        .map(|(name, force)| format!("{name}: {:.3}", force.length()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let position = app
+        .window_rect()
+        .pad_top(250.0)
+        .pad_right(100.0)
+        .top_right();
+    draw.text(
+        format!(
+            "Speed: {:.2}\nHeading: {:.0}°\nNeighbours: {}\n{}\nTotal: {:.3}",
+            boid.velocity.length(),
+            boid.velocity.angle().to_degrees(),
+            boid.neighbour_count,
+            forces,
+            boid.forces.total().length(),
         )
-        .left_justify()
-        .color(BLACK)
-        .xy(position);
+        .trim(),
+    )
+    .left_justify()
+    .color(model.theme().text)
+    .xy(position);
+}
+
+// The collapsible control panel. Everything here can also be changed from the keyboard.
+pub fn update_ui(app: &App, model: &mut Model) {
+    let mut ui = model.gui.begin(app, app.window_rect());
+    if !ui.title("Controls") {
+        return;
+    }
+    let mut share = false;
+    let mut world = None;
+    let mut compare = false;
+    let mut restart = false;
+
+    let sim = &mut model.sims[model.active];
+
+    if ui.section("Flocking") {
+        // Shortcuts to the prey behaviours people reach for most.
+        for behaviour in ["Alignment", "Cohesion", "Separation"] {
+            if let Some(weight) = sim.boid_options.steering.weight_mut(behaviour) {
+                ui.slider(behaviour, weight, MODIFIER_RANGE);
+            }
+        }
+        ui.slider("Max force", &mut sim.max_force, MAX_FORCE_RANGE);
+        if ui.button(format!("Cursor: {:?}", model.cursor_mode)) {
+            model.cursor_mode = model.cursor_mode.next();
+        }
+    }
+    if ui.section("Prey") {
+        species_controls(&mut ui, &mut sim.boid_options, MAX_PREY);
+    }
+    if ui.section("Predators") {
+        species_controls(&mut ui, &mut sim.predator_options, MAX_PREDATORS);
+    }
+    if ui.section("Leaders & Paths") {
+        ui.slider_usize("Leaders", &mut model.leader_count, 0..=20);
+        ui.slider("Leader weight", &mut model.leader_weight, 1.0..=20.0);
+        if ui.button(format!("Leader target: {:?}", model.leader_target)) {
+            model.leader_target = model.leader_target.next();
+        }
+        ui.toggle("Follow queue", &mut model.follow_queue);
+        if ui.button(format!("Path: {:?}", model.path_mode)) {
+            model.path_mode = model.path_mode.next();
+        }
+        ui.slider("Path weight", &mut model.path_weight, 0.0..=5.0);
+        ui.slider("Path width", &mut model.path.width, 10.0..=150.0);
+    }
+    if ui.section("Time") {
+        ui.toggle("Paused", &mut model.clock.paused);
+        ui.slider("Time scale", &mut model.clock.time_scale, TIME_SCALE_RANGE);
+        if ui.button("Step".to_string()) {
+            model.clock.step_once();
+        }
+        ui.slider_usize("Rewind seconds", &mut model.history.seconds, 0..=60);
+    }
+    if ui.section("Compare") {
+        let label = if model.sims.len() > 1 {
+            "Back to one simulation"
+        } else {
+            "Compare side by side"
+        };
+        compare = ui.button(label.to_string());
+        let side = if model.active == 0 { "left" } else { "right" };
+        if model.sims.len() > 1 && ui.button(format!("Editing: {side}")) {
+            model.active = (model.active + 1) % model.sims.len();
+        }
+        restart = ui.button("Restart from seed".to_string());
+    }
+    if ui.section("World") {
+        let mut size = model.world.wh();
+        ui.slider("Width", &mut size.x, WORLD_SIZE_RANGE);
+        ui.slider("Height", &mut size.y, WORLD_SIZE_RANGE);
+        if size != model.world.wh() {
+            world = Some(Rect::from_xy_wh(model.world.xy(), size));
+        }
+        ui.slider("Zoom", &mut model.camera.zoom, ZOOM_RANGE);
+        if ui.button(format!("On resize: {:?}", model.resize_mode)) {
+            model.resize_mode = model.resize_mode.next();
+        }
+        if ui.button("Reset view".to_string()) {
+            model.camera.reset(model.world, app.window_rect());
+        }
+    }
+    if ui.section("Presets") {
+        for i in 0..model.presets.len() {
+            if ui.button(format!("{}: {}", i + 1, model.presets[i].name)) {
+                model.preset_target = Some(model.presets[i].clone());
+            }
+        }
+        share = ui.button("Share current settings".to_string());
+        if let Some(code) = ui.text_field("Load code", "type a code or file") {
+            match Preset::load(&code) {
+                Ok(preset) => model.preset_target = Some(preset),
+                Err(e) => eprintln!("Ignoring preset code {code:?}: {e}"),
+            }
+        }
+    }
+    if ui.section("Display") {
+        if ui.button(format!("Theme: {}", model.themes[model.theme_index].name)) {
+            model.theme_index = (model.theme_index + 1) % model.themes.len();
+        }
+        if ui.button(format!("Colour: {:?}", model.colour_mode)) {
+            model.colour_mode = model.colour_mode.next();
+        }
+        if model.colour_mode != ColourMode::Species && ui.button("Next gradient".to_string()) {
+            let gradient = model.gradients[&model.colour_mode].next();
+            model.gradients.insert(model.colour_mode, gradient);
+        }
+        if ui.button(format!("Debug overlay: {:?}", model.debug_overlay)) {
+            model.debug_overlay = model.debug_overlay.next();
+        }
+        ui.toggle("Trails", &mut model.show_trails);
+        ui.slider_usize("Trail length", &mut model.trail_length, 2..=200);
+        ui.slider("Trail opacity", &mut model.trail_opacity, 0.0..=1.0);
+        ui.toggle("Show text", &mut model.show_text);
+    }
+    // These need the whole model, so they wait until the panel is done with it.
+    drop(ui);
+    if share {
+        perform(app, model, Action::ExportPreset);
+    }
+    if compare {
+        model.toggle_comparison();
+    }
+    if restart {
+        model.restart();
+    }
+    if let Some(world) = world {
+        model.set_world(world);
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn species_controls(ui: &mut Layout, options: &mut BoidOptions, max_count: usize) {
+    let mut count = options.flock_size;
+    ui.slider_usize("Count", &mut count, 0..=max_count);
+    if count != options.flock_size {
+        options.n_mod = count as i32 - options.flock_size as i32;
+    }
+    ui.slider("Min speed", &mut options.min_speed, SPEED_RANGE);
+    ui.slider("Max speed", &mut options.max_speed, SPEED_RANGE);
+    ui.slider(
+        "Start speed",
+        &mut options.starting_velocity,
+        START_SPEED_RANGE,
+    );
+    ui.slider("Visual range", &mut options.visual_range, VISION_RANGE);
+    ui.slider(
+        "Protected range",
+        &mut options.protected_range,
+        PROTECTION_RANGE,
+    );
+    if ui.button(format!("Neighbours: {:?}", options.neighbourhood)) {
+        options.neighbourhood = options.neighbourhood.next();
+    }
+    if let Neighbourhood::Topological(k) | Neighbourhood::Hybrid(k) = &mut options.neighbourhood {
+        ui.slider_usize("Nearest k", k, NEAREST_K_RANGE);
+    }
+    // Every registered behaviour, switched on or off, with a weight for those in use.
+    for weighted in &mut options.steering.behaviours {
+        ui.toggle(weighted.behaviour.name(), &mut weighted.enabled);
+        if weighted.enabled {
+            ui.slider("  Weight", &mut weighted.weight, MODIFIER_RANGE);
+        }
     }
 }
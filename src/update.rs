@@ -1,98 +1,81 @@
+use crate::{
+    history::update_timeline,
+    model::Model,
+    session::{broadcast, update_session},
+    ui::update_ui,
+};
 use nannou::{prelude::Update, App};
-// use ui::update_ui;
-use crate::{boids::align, model::Model};
 
-pub fn update(app: &App, model: &mut Model, _update: Update) {
-    update_model(app, model);
-    if model.flock.is_empty() {
-        return;
-    };
-    for i in 0..model.predators.len() {
-        let close_predators = model.predators[i].close_predators(&model.predators);
-        let (mut nearby_boids, close_boids) = model.predators[i].get_neighbours(&model.flock);
-        nearby_boids.extend(close_boids);
-        let separation = model.predators[i].separate(&close_predators);
-        let hunting_force = model.predators[i].cohere(&nearby_boids);
-        let bounds_force = model.predators[i].avoid_bounds(
-            &app.window(model.main_window)
-                .expect("Problem retrieving main window")
-                .rect(),
-        );
+const PRESET_BLEND_RATE: f32 = 0.05;
 
-        model.predators[i].acceleration += hunting_force + bounds_force + separation;
-        model.predators[i].update();
+pub fn update(app: &App, model: &mut Model, _update: Update) {
+    update_ui(app, model);
+    update_timeline(app, model);
+    update_camera(app, model);
+    update_model(model);
+    model.registry.refresh();
+    let mut env = model.environment(app);
+    update_session(app, model, &mut env);
+    // When following a host, the boids arrive already moved.
+    let following = model.session.as_ref().is_some_and(|s| s.following());
+    let steps = if following { 0 } else { model.clock.steps() };
+    if steps > 0 {
+        model.history.resume();
     }
-
-    for i in 0..model.flock.len() {
-        let (nearby_boids, close_boids) = model.flock[i].get_neighbours(&model.flock);
-        let alignment = align(&nearby_boids) * model.alignment_modifier;
-        let seperation = model.flock[i].separate(&close_boids) * model.separation_modifier;
-        let cohesion = model.flock[i].cohere(&nearby_boids) * model.cohesion_modifier;
-        let predator_avoidance = model.flock[i].avoid_predators(&model.predators);
-        let cursor_interaction = model.flock[i].cursor_interaction(app, &model.cursor_mode);
-        let bounds_force = model.flock[i].avoid_bounds(
-            &app.window(model.main_window)
-                .expect("Problem retrieving main window")
-                .rect(),
-        );
-        model.flock[i].acceleration += alignment
-            + seperation
-            + cohesion
-            + bounds_force
-            + predator_avoidance
-            + cursor_interaction;
-
-        model.flock[i].wrap(
-            &app.window(model.main_window)
-                .expect("Problem retrieving main window")
-                .rect(),
-        );
-        model.flock[i].update();
+    let mut catches = Vec::new();
+    for _ in 0..steps {
+        for sim in &mut model.sims {
+            sim.step(&env);
+        }
+        catches.extend(model.sim().catches.iter().cloned());
+        model.history.record(&model.sims);
+    }
+    broadcast(model, &env);
+    if let Some(osc) = &mut model.osc {
+        osc.send(&model.sims[model.active], model.world, &catches);
+    }
+    #[cfg(feature = "websocket")]
+    if let Some(websocket) = &mut model.websocket {
+        websocket.send(&model.sims[model.active], model.world, &catches);
+    }
+    if let Some(mut recording) = model.recording.take() {
+        match recording.record(app, model, &catches) {
+            Ok(()) => model.recording = Some(recording),
+            Err(e) => {
+                eprintln!("{e}. Stopping the recording.");
+                recording.finish();
+            }
+        }
+    }
+    for sim in &mut model.sims {
+        // Boids can still be added or removed while paused, and the grid has to keep up.
+        if steps == 0 {
+            sim.rebuild_grid();
+        }
+        sim.update_colour_data(model.colour_mode);
     }
 }
 
-fn update_model(app: &App, model: &mut Model) {
-    adjust_boid_count(app, model);
+fn update_camera(app: &App, model: &mut Model) {
+    let (_, mouse) = model.mouse_in_view(app);
+    model.camera.drag(mouse, app.mouse.buttons.left().is_down());
+    model.camera.clamp_to(model.world);
 }
 
-fn adjust_boid_count(app: &App, model: &mut Model) {
-    if model.predator_options.n_mod == 0 && model.boid_options.n_mod == 0
-        || model.predator_options.flock_size == 0 && model.boid_options.flock_size == 0
-    {
-        return;
+fn update_model(model: &mut Model) {
+    blend_preset(model);
+    for sim in &mut model.sims {
+        sim.apply_settings(model.world, model.leader_count);
     }
+    model.leader_count = model.leader_count.min(model.sim().flock.len());
+}
 
-    let bounds = app
-        .window(model.main_window)
-        .expect("Main window should be running")
-        .rect();
-
-    // if n_mod isn't 0, and flock/predators isn't empty or 1000, increase by n_mod
-    // then set n_mod to 0
-
-    for _ in 0..model.predator_options.n_mod.abs() {
-        match model.predator_options.n_mod.signum() {
-            1 => {
-                for _ in 0..model.predator_options.n_mod {
-                    model.add_boid(bounds, model.predator_options.boid_type);
-                }
-            }
-            -1 => model.remove_boid(model.predator_options.boid_type),
-            0 => {}
-            _ => panic!("Unreachable match condition"),
-        };
-    }
-    model.predator_options.n_mod = 0;
-    model.predator_options.flock_size = model.predators.len();
-
-    for _ in 0..model.boid_options.n_mod.abs() {
-        match model.boid_options.n_mod.signum() {
-            1 => model.add_boid(bounds, model.boid_options.boid_type),
-            -1 => model.remove_boid(model.boid_options.boid_type),
-            0 => {}
-            _ => panic!("Unreachable match condition"),
-        };
+// Eases settings towards a newly chosen preset over a second or so, rather
+// than jumping straight there.
+fn blend_preset(model: &mut Model) {
+    if let Some(preset) = model.preset_target.take() {
+        if !preset.blend(model, PRESET_BLEND_RATE) {
+            model.preset_target = Some(preset);
+        }
     }
-    model.boid_options.n_mod = 0;
-    model.boid_options.flock_size = model.flock.len();
 }
@@ -0,0 +1,496 @@
+use std::{
+    io::ErrorKind,
+    net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use tungstenite::{
+    handshake::{
+        server::{NoCallback, ServerHandshake},
+        HandshakeError, MidHandshake,
+    },
+    protocol::WebSocketConfig,
+    Error, Message, WebSocket,
+};
+
+use crate::{
+    boids::Boid,
+    model::{arg, RATE_RANGE},
+    simulation::{Catch, Metrics, Simulation},
+};
+
+// A watcher that falls this far behind is dropped, rather than queueing
+// frames for it forever.
+const MAX_BACKLOG: usize = 4 << 20;
+// Connections that haven't finished saying hello by then are dropped, and no
+// more than this many are kept waiting at once.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_HANDSHAKES: usize = 16;
+
+type Handshaking = ServerHandshake<TcpStream, NoCallback>;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    Json,
+    Binary,
+}
+
+// What a watcher sends to change what it gets. Anything left out stays as it was.
+#[derive(Deserialize)]
+struct Subscription {
+    format: Option<Format>,
+    // Frames a second, or every frame at 0.
+    rate: Option<f32>,
+}
+
+// A frame as sent to JSON watchers. Kept as f32 so numbers aren't padded out
+// to double precision.
+#[derive(Serialize)]
+struct JsonFrame {
+    frame: u32,
+    world: [f32; 2],
+    metrics: JsonMetrics,
+    catches: Vec<JsonCatch>,
+    prey: Vec<[f32; 4]>,
+    predators: Vec<[f32; 4]>,
+}
+
+#[derive(Serialize)]
+struct JsonMetrics {
+    polarisation: f32,
+    mean_speed: f32,
+    mean_neighbours: f32,
+    clusters: usize,
+}
+
+#[derive(Serialize)]
+struct JsonCatch {
+    predator: usize,
+    prey: usize,
+    x: f32,
+    y: f32,
+}
+
+// Streams the flock to browser dashboards and other visualisers over
+// WebSockets, so they can mirror the simulation without linking against nannou.
+//
+// Every watcher gets a frame after each update, as JSON text:
+//
+//   {"frame": 120, "world": [w, h],
+//    "metrics": {"polarisation", "mean_speed", "mean_neighbours", "clusters"},
+//    "catches": [{"predator", "prey", "x", "y"}],
+//    "prey": [[x, y, vx, vy], ...], "predators": [[x, y, vx, vy], ...]}
+//
+// or, after sending {"format": "binary"}, as a little endian binary message:
+//
+//   frame                                  u32
+//   world width and height                 f32, f32
+//   polarisation, mean speed, neighbours   f32, f32, f32
+//   clusters                               u32
+//   catch count, prey count, predators     u32, u32, u32
+//   each catch                             predator u32, prey u32, x f32, y f32
+//   each boid                              x f32, y f32, vx f32, vy f32
+//
+// Sending {"rate": 4} slows a watcher down to ten frames a second, and
+// {"rate": 0} goes back to every frame. Other rates outside `RATE_RANGE` are
+// ignored. Catches are only in the frames that
+// are sent, so a slowed watcher misses those in between.
+//
+// Positions are in points from the centre of the world, and velocities in
+// points a step.
+pub struct WebSocketServer {
+    listener: TcpListener,
+    port: u16,
+    // Each with when the connection was let in.
+    handshakes: Vec<(MidHandshake<Handshaking>, Instant)>,
+    watchers: Vec<Watcher>,
+    frame: u32,
+}
+
+struct Watcher {
+    socket: WebSocket<TcpStream>,
+    format: Format,
+    interval: Option<Duration>,
+    last_sent: Option<Instant>,
+}
+
+impl WebSocketServer {
+    // Listens on `--websocket <port>`, if given.
+    pub fn from_args() -> Option<WebSocketServer> {
+        let port = arg("--websocket")?;
+        let port: u16 = port
+            .parse()
+            .map_err(|_| eprintln!("Not streaming: {port:?} isn't a port number"))
+            .ok()?;
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+            .map_err(|e| eprintln!("Not streaming on port {port}: {e}"))
+            .ok()?;
+        println!("Streaming the flock on ws://localhost:{port}");
+        Some(WebSocketServer {
+            listener,
+            port,
+            handshakes: Vec::new(),
+            watchers: Vec::new(),
+            frame: 0,
+        })
+    }
+
+    pub fn status(&self) -> String {
+        format!(
+            "Streaming on port {}: {} watching",
+            self.port,
+            self.watchers.len()
+        )
+    }
+
+    // Lets in new watchers, hears from existing ones, then sends this frame
+    // to everyone it's due to.
+    pub fn send(&mut self, sim: &Simulation, world: Rect, catches: &[Catch]) {
+        self.accept();
+        self.watchers.retain_mut(Watcher::listen);
+        self.frame = self.frame.wrapping_add(1);
+
+        let now = Instant::now();
+        let (mut metrics, mut json, mut binary) = (None, None, None);
+        self.watchers.retain_mut(|watcher| {
+            let due = match (watcher.interval, watcher.last_sent) {
+                (Some(interval), Some(last_sent)) => now.duration_since(last_sent) >= interval,
+                _ => true,
+            };
+            if !due {
+                return true;
+            }
+            watcher.last_sent = Some(now);
+            // The metrics and each format are only worked out once a frame,
+            // and only if someone wants them.
+            let metrics = metrics.get_or_insert_with(|| sim.metrics());
+            let message = match watcher.format {
+                Format::Json => Message::Text(
+                    json.get_or_insert_with(|| {
+                        encode_json(self.frame, sim, metrics, world, catches)
+                    })
+                    .clone(),
+                ),
+                Format::Binary => Message::Binary(
+                    binary
+                        .get_or_insert_with(|| {
+                            encode_binary(self.frame, sim, metrics, world, catches)
+                        })
+                        .clone(),
+                ),
+            };
+            still_there(watcher.socket.send(message))
+        });
+    }
+
+    fn accept(&mut self) {
+        let now = Instant::now();
+        let mut handshakes = Vec::new();
+        // Handshakes that were still waiting on the watcher pick up where they
+        // left off, unless they've been waiting too long.
+        for (handshake, started) in std::mem::take(&mut self.handshakes) {
+            if now.duration_since(started) > HANDSHAKE_TIMEOUT {
+                eprintln!("Dropping a watcher that never finished connecting");
+                continue;
+            }
+            self.handshaken(handshake.handshake(), started, &mut handshakes);
+        }
+        while let Ok((stream, _)) = self.listener.accept() {
+            // Dropping the stream turns the connection away.
+            if handshakes.len() >= MAX_HANDSHAKES {
+                eprintln!("Turning a watcher away: too many still connecting");
+                continue;
+            }
+            if let Err(e) = stream.set_nonblocking(true) {
+                eprintln!("Couldn't let a watcher in: {e}");
+                continue;
+            }
+            let _ = stream.set_nodelay(true);
+            let config = WebSocketConfig {
+                max_write_buffer_size: MAX_BACKLOG,
+                ..WebSocketConfig::default()
+            };
+            self.handshaken(
+                tungstenite::accept_with_config(stream, Some(config)),
+                now,
+                &mut handshakes,
+            );
+        }
+        self.handshakes = handshakes;
+    }
+
+    fn handshaken(
+        &mut self,
+        result: Result<WebSocket<TcpStream>, HandshakeError<Handshaking>>,
+        started: Instant,
+        pending: &mut Vec<(MidHandshake<Handshaking>, Instant)>,
+    ) {
+        match result {
+            Ok(socket) => self.watchers.push(Watcher::new(socket)),
+            Err(HandshakeError::Interrupted(handshake)) => pending.push((handshake, started)),
+            Err(HandshakeError::Failure(e)) => eprintln!("Couldn't let a watcher in: {e}"),
+        }
+    }
+}
+
+impl Watcher {
+    fn new(socket: WebSocket<TcpStream>) -> Watcher {
+        Watcher {
+            socket,
+            format: Format::Json,
+            interval: None,
+            last_sent: None,
+        }
+    }
+
+    // Reads any subscriptions waiting, and pushes out anything left over from
+    // earlier frames. False once the watcher has gone.
+    fn listen(&mut self) -> bool {
+        loop {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => self.subscribe(&text),
+                Ok(_) => {}
+                Err(e) => {
+                    if !still_there(Err(e)) {
+                        return false;
+                    }
+                    break;
+                }
+            }
+        }
+        still_there(self.socket.flush())
+    }
+
+    fn subscribe(&mut self, text: &str) {
+        match serde_json::from_str::<Subscription>(text) {
+            Ok(subscription) => {
+                if let Some(format) = subscription.format {
+                    self.format = format;
+                }
+                match subscription.rate {
+                    Some(0.0) => self.interval = None,
+                    Some(rate) if RATE_RANGE.contains(&rate) => {
+                        self.interval = Some(Duration::from_secs_f32(1.0 / rate));
+                    }
+                    Some(rate) => eprintln!(
+                        "Ignoring rate {rate} from a watcher, which should be 0 or from {} to {}",
+                        RATE_RANGE.start(),
+                        RATE_RANGE.end()
+                    ),
+                    None => {}
+                }
+            }
+            Err(e) => eprintln!("Ignoring subscription {text:?}: {e}"),
+        }
+    }
+}
+
+// Whether the watcher is still there after a read or write. Waiting on a
+// slow network is fine; anything else means it's gone or too far behind.
+fn still_there(result: Result<(), Error>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => true,
+        Err(Error::ConnectionClosed | Error::AlreadyClosed) => false,
+        Err(e) => {
+            eprintln!("Dropping a watcher: {e}");
+            false
+        }
+    }
+}
+
+fn encode_json(
+    frame: u32,
+    sim: &Simulation,
+    metrics: &Metrics,
+    world: Rect,
+    catches: &[Catch],
+) -> String {
+    let boids = |boids: &[Boid]| -> Vec<[f32; 4]> {
+        boids
+            .iter()
+            .map(|b| [b.position.x, b.position.y, b.velocity.x, b.velocity.y])
+            .collect()
+    };
+    let frame = JsonFrame {
+        frame,
+        world: [world.w(), world.h()],
+        metrics: JsonMetrics {
+            polarisation: metrics.polarisation,
+            mean_speed: metrics.mean_speed,
+            mean_neighbours: metrics.mean_neighbours,
+            clusters: metrics.clusters,
+        },
+        catches: catches
+            .iter()
+            .map(|catch| JsonCatch {
+                predator: catch.predator,
+                prey: catch.prey,
+                x: catch.position.x,
+                y: catch.position.y,
+            })
+            .collect(),
+        prey: boids(&sim.flock),
+        predators: boids(&sim.predators),
+    };
+    serde_json::to_string(&frame).expect("frames are always valid JSON")
+}
+
+fn encode_binary(
+    frame: u32,
+    sim: &Simulation,
+    metrics: &Metrics,
+    world: Rect,
+    catches: &[Catch],
+) -> Vec<u8> {
+    let count = |n: usize| u32::try_from(n).unwrap_or(u32::MAX).to_le_bytes();
+    let mut bytes =
+        Vec::with_capacity(40 + 16 * (catches.len() + sim.flock.len() + sim.predators.len()));
+    bytes.extend(frame.to_le_bytes());
+    for value in [
+        world.w(),
+        world.h(),
+        metrics.polarisation,
+        metrics.mean_speed,
+        metrics.mean_neighbours,
+    ] {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.extend(count(metrics.clusters));
+    bytes.extend(count(catches.len()));
+    bytes.extend(count(sim.flock.len()));
+    bytes.extend(count(sim.predators.len()));
+    for catch in catches {
+        bytes.extend(count(catch.predator));
+        bytes.extend(count(catch.prey));
+        bytes.extend(catch.position.x.to_le_bytes());
+        bytes.extend(catch.position.y.to_le_bytes());
+    }
+    for boid in sim.flock.iter().chain(&sim.predators) {
+        for value in [
+            boid.position.x,
+            boid.position.y,
+            boid.velocity.x,
+            boid.velocity.y,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use tungstenite::protocol::Role;
+
+    use super::*;
+    use crate::model::{registry, BoidOptions};
+
+    fn simulation(world: Rect) -> Simulation {
+        let registry = registry();
+        let mut prey = BoidOptions::prey(&registry);
+        prey.flock_size = 20;
+        let mut predators = BoidOptions::predators(&registry);
+        predators.flock_size = 2;
+        Simulation::new(3, world, prey, predators)
+    }
+
+    // A watcher on one end of a local connection, with no handshake.
+    fn watcher() -> (Watcher, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        (Watcher::new(socket), client)
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn f32_at(bytes: &[u8], at: usize) -> f32 {
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn binary_frames_follow_the_documented_layout() {
+        let world = Rect::from_w_h(1200.0, 700.0);
+        let sim = simulation(world);
+        let metrics = sim.metrics();
+        let catches = [Catch {
+            position: vec2(12.5, -40.0),
+            predator: 1,
+            prey: 7,
+        }];
+        let bytes = encode_binary(42, &sim, &metrics, world, &catches);
+
+        assert_eq!(u32_at(&bytes, 0), 42);
+        assert_eq!(f32_at(&bytes, 4), 1200.0);
+        assert_eq!(f32_at(&bytes, 8), 700.0);
+        assert_eq!(f32_at(&bytes, 12), metrics.polarisation);
+        assert_eq!(f32_at(&bytes, 16), metrics.mean_speed);
+        assert_eq!(f32_at(&bytes, 20), metrics.mean_neighbours);
+        assert_eq!(u32_at(&bytes, 24) as usize, metrics.clusters);
+        assert_eq!(u32_at(&bytes, 28), 1);
+        assert_eq!(u32_at(&bytes, 32), 20);
+        assert_eq!(u32_at(&bytes, 36), 2);
+
+        assert_eq!(u32_at(&bytes, 40), 1);
+        assert_eq!(u32_at(&bytes, 44), 7);
+        assert_eq!(f32_at(&bytes, 48), 12.5);
+        assert_eq!(f32_at(&bytes, 52), -40.0);
+
+        // Prey come first, then predators.
+        let boids: Vec<&Boid> = sim.flock.iter().chain(&sim.predators).collect();
+        assert_eq!(bytes.len(), 56 + 16 * boids.len());
+        for (i, boid) in boids.iter().enumerate() {
+            let at = 56 + 16 * i;
+            assert_eq!(f32_at(&bytes, at), boid.position.x);
+            assert_eq!(f32_at(&bytes, at + 4), boid.position.y);
+            assert_eq!(f32_at(&bytes, at + 8), boid.velocity.x);
+            assert_eq!(f32_at(&bytes, at + 12), boid.velocity.y);
+        }
+    }
+
+    #[test]
+    fn subscriptions_change_format_and_rate() {
+        let (mut watcher, _client) = watcher();
+        assert_eq!(watcher.format, Format::Json);
+        assert_eq!(watcher.interval, None);
+
+        watcher.subscribe(r#"{"format": "binary"}"#);
+        assert_eq!(watcher.format, Format::Binary);
+        assert_eq!(watcher.interval, None);
+
+        // Leaving the format out keeps it as it was.
+        watcher.subscribe(r#"{"rate": 4}"#);
+        assert_eq!(watcher.format, Format::Binary);
+        assert_eq!(watcher.interval, Some(Duration::from_millis(250)));
+
+        // Rates that can't be turned into an interval, and anything that
+        // isn't a subscription, are ignored.
+        for ignored in [
+            r#"{"rate": 1e-30}"#,
+            r#"{"rate": -5}"#,
+            r#"{"rate": 1e9}"#,
+            r#"{"format": "xml"}"#,
+            "hello",
+        ] {
+            watcher.subscribe(ignored);
+            assert_eq!(watcher.format, Format::Binary, "{ignored}");
+            assert_eq!(
+                watcher.interval,
+                Some(Duration::from_millis(250)),
+                "{ignored}"
+            );
+        }
+
+        watcher.subscribe(r#"{"format": "json", "rate": 0}"#);
+        assert_eq!(watcher.format, Format::Json);
+        assert_eq!(watcher.interval, None);
+    }
+}